@@ -13,8 +13,9 @@ use rand::Rng;
 use reqwest::{
     cookie::Jar,
     header::{self, HeaderMap, HeaderValue},
-    multipart, Client, Url,
+    multipart, Client, RequestBuilder, Url,
 };
+use scraper::{Html, Selector};
 use serde_json::{json, Value};
 use tokio::{net::TcpStream, time};
 use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
@@ -22,23 +23,30 @@ use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
 use crate::{
     bot::BotInfo,
     bundles::PoeBundle,
-    chat::Chat,
+    chat::{
+        Chat, ChatContext, ChatDeletion, ChatPage, ChatSettings, DeletionStatus, SharedChat,
+        SharedMessage,
+    },
+    compare::Comparison,
     constants::{
         default_headers, subscriptions_mutation, BASE_URL, BOT_NICKNAME, DEFAULT_CATEGORY_NAME,
     },
+    events::AccountEvents,
+    fork::{ChatFork, ForkMode, ForkTree, FORK_REPLAY_PROMPT},
     history::ChatHistory,
-    message::MessageContext,
+    message::{MessageContext, MessageTimeouts},
     models::{
         api_settings::{ApiSettings, MySettings},
         message::{Message, MessageEdgeCreate},
         on_message::MessageQueue,
         query::QueryHash,
+        reply::Reply,
         user::UserInfo,
-        SearchData, SendMessageData, Token,
+        FileData, FileInput, SearchData, SendMessageData, Token,
     },
     queries::{RequestData, RequestPath},
     search::SearchResult,
-    utils::{generate_file, generate_nonce, get_json_value},
+    utils::{find_json_key, generate_file, generate_nonce, get_json_value, parse_share_code},
 };
 
 type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
@@ -52,6 +60,11 @@ pub struct PoeApi {
 
     // data
     pub message_queues: MessageQueueData,
+    pub message_timeouts: MessageTimeouts,
+    /// Chat settings by chat id, see [`PoeApi::get_chat_settings`].
+    pub chat_settings: HashMap<i64, ChatSettings>,
+    /// Forks made through `ChatContext::fork`.
+    pub forks: ForkTree,
 
     // WebSocket Data
     pub stream_writer: Option<SplitSink<WsStream, tungstenite::Message>>,
@@ -87,6 +100,9 @@ impl PoeApi {
 
             // data
             message_queues: HashMap::new(),
+            message_timeouts: MessageTimeouts::default(),
+            chat_settings: HashMap::new(),
+            forks: ForkTree::default(),
 
             // websocket
             stream_writer: None,
@@ -161,6 +177,14 @@ impl PoeApi {
      */
 
     pub async fn send_request(&mut self, request_data: RequestData) -> anyhow::Result<Value> {
+        let request = self.build_request(request_data).await?;
+        execute_request(request).await
+    }
+
+    pub(crate) async fn build_request(
+        &mut self,
+        request_data: RequestData,
+    ) -> anyhow::Result<RequestBuilder> {
         // TODO: Handle rate limit
 
         let formkey = if let Some(formkey) = self.default_headers.get("Poe-Formkey") {
@@ -174,7 +198,8 @@ impl PoeApi {
                 "Waiting queue {}/2 to avoid rate limit",
                 request_data.ratelimit
             );
-            time::sleep(Duration::from_secs(rand::rng().random_range(2..=3))).await;
+            let wait = rand::rng().random_range(2..=3);
+            time::sleep(Duration::from_secs(wait)).await;
         }
         let payload = serde_json::to_string(&request_data.generate_payload())?;
         let mut base_string = payload.clone();
@@ -219,28 +244,7 @@ impl PoeApi {
         headers.insert("poe-tag-id", HeaderValue::from_str(&tag)?);
         request = request.headers(headers);
 
-        let response = request.send().await?;
-        let data = response.json::<Value>().await?;
-
-        let is_success = data
-            .get("success")
-            .map_or(false, |v| v.as_bool().unwrap_or(false));
-        if !is_success || data.get("data").is_none() {
-            if let Some(err) = get_json_value(&data, "errors.0.message")
-            {
-                let msg = Value::as_str(err).unwrap_or("");
-                if msg == "Server Error" {
-                    anyhow::bail!(
-                        "Server Error. Raw response data: {:?}",
-                        serde_json::to_string(&data)?
-                    );
-                } else {
-                    anyhow::bail!("{}", msg);
-                }
-            }
-        }
-
-        Ok(data)
+        Ok(request)
     }
 
     /*
@@ -276,55 +280,164 @@ impl PoeApi {
                 ..Default::default()
             })
             .await?;
-        let is_success = get_json_value(&response, "data.setAllChatDefaultMessagePointPriceThreshold")
+        let is_success = get_json_value(
+            &response,
+            "data.setAllChatDefaultMessagePointPriceThreshold",
+        )
+        .map(|v| v.is_object())
+        .unwrap_or(false);
+        Ok(is_success)
+    }
+
+    /// Caps the points a single message may cost in this chat. Messages from
+    /// bots priced above it are refused instead of sent.
+    pub async fn set_chat_point_threshold(
+        &mut self,
+        chat_id: i64,
+        points: usize,
+    ) -> anyhow::Result<bool> {
+        let response = self
+            .send_request(RequestData {
+                query_name: QueryHash::CostThresholdUpdateChatModal_ChatSetMessagePointPriceThreshold_Mutation,
+                data: json!({
+                    "chatId": chat_id,
+                    "priceThresholdInPoints": points.to_string()
+                }),
+                ..Default::default()
+            })
+            .await?;
+        let is_success = get_json_value(&response, "data.chatSetMessagePointPriceThreshold")
             .map(|v| v.is_object())
             .unwrap_or(false);
+        if is_success {
+            if let Some(settings) = self.chat_settings.get_mut(&chat_id) {
+                settings.point_threshold = Some(points as i64);
+            }
+        }
         Ok(is_success)
     }
 
+    /// Current per-chat point threshold, `None` when the chat uses the account default.
+    pub async fn get_chat_point_threshold(
+        &mut self,
+        chat_code: &str,
+    ) -> anyhow::Result<Option<i64>> {
+        Ok(self.get_chat_settings(chat_code).await?.point_threshold)
+    }
+
     /*
      * +-------------------------+
      * | Conversation Management |
      * +-------------------------+
      */
 
-    pub fn chat_history(&mut self) -> ChatHistory {
+    pub fn chat_history(&mut self) -> ChatHistory<'_> {
         ChatHistory::new(self)
     }
 
-    pub async fn import_chat(&mut self, chat_code: &str) -> anyhow::Result<Option<Chat>> {
-        // get bot name
-        let url = format!("{}/s/{}", BASE_URL, chat_code);
-        let response = self.client.get(&url).send().await?;
-        let html = response.text().await?;
+    /// Continues a shared chat in your account. `share` is a share code or a
+    /// share URL.
+    pub async fn import_chat(&mut self, share: &str) -> anyhow::Result<Chat> {
+        let share_code = parse_share_code(share);
+        let (html, page_data) = self.shared_page(share_code).await?;
+        let bot_name = page_data
+            .as_ref()
+            .and_then(|data| find_json_key(data, "nickname"))
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string())
+            .or_else(|| {
+                BOT_NICKNAME
+                    .captures(&html)
+                    .and_then(|cap| cap.get(1))
+                    .map(|v| v.as_str().to_string())
+            })
+            .ok_or(anyhow::anyhow!("failed to get bot name from poe webpage."))?;
+        self.continue_shared_chat(share_code, &bot_name).await
+    }
 
-        if let Some(cap) = BOT_NICKNAME.captures(&html) {
-            if let Some(botname) = cap.get(1).map(|v| v.as_str()) {
-                let response = self
-                    .send_request(RequestData {
-                        query_name:
-                            QueryHash::ContinueChatCTAButton_continueChatFromPoeShare_Mutation,
-                        data: json!({
-                            "shareCode": chat_code,
-                            "botName": botname,
-                            "postId": null
-                        }),
-                        ..Default::default()
-                    })
-                    .await?;
-                if let Some(data) = get_json_value(&response, "data.continueChatFromPoeShare") {
-                    let status = get_json_value(data, "status").and_then(|v| v.as_str()).unwrap_or("");
-                    if let Some(status_message) = get_json_value(data, "statusMessage").and_then(|v| v.as_str())
-                    {
-                        if !status_message.is_empty() {
-                            anyhow::bail!(format!("{}: {}", status, status_message));
-                        }
-                    }
-                    return Ok(self.chat_history().next().await);
-                }
+    /// Continues a shared chat with `bot_name`, which may differ from the bot
+    /// of the shared messages.
+    pub(crate) async fn continue_shared_chat(
+        &mut self,
+        share_code: &str,
+        bot_name: &str,
+    ) -> anyhow::Result<Chat> {
+        let response = self
+            .send_request(RequestData {
+                query_name: QueryHash::ContinueChatCTAButton_continueChatFromPoeShare_Mutation,
+                data: json!({
+                    "shareCode": share_code,
+                    "botName": bot_name,
+                    "postId": null
+                }),
+                ..Default::default()
+            })
+            .await?;
+        let data = get_json_value(&response, "data.continueChatFromPoeShare")
+            .ok_or(anyhow::anyhow!("failed to import chat {}", share_code))?;
+        let status = get_json_value(data, "status")
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+        if let Some(status_message) = get_json_value(data, "statusMessage").and_then(|v| v.as_str())
+        {
+            if !status_message.is_empty() {
+                anyhow::bail!(format!("{}: {}", status, status_message));
             }
         }
-        anyhow::bail!("failed to get bot name from poe webpage.")
+        match get_json_value(data, "chat").filter(|v| !v.is_null()) {
+            Some(chat) => Ok(serde_json::from_value(chat.clone())?),
+            None => anyhow::bail!("{}: no chat in the import response", status),
+        }
+    }
+
+    /// Reads a shared chat without importing it. `share` is a share code or a
+    /// share URL.
+    pub async fn preview_shared_chat(&mut self, share: &str) -> anyhow::Result<SharedChat> {
+        let share_code = parse_share_code(share);
+        let (_, page_data) = self.shared_page(share_code).await?;
+        let data = page_data.ok_or(anyhow::anyhow!("no chat data in share page {}", share_code))?;
+
+        let messages = find_json_key(&data, "messages")
+            .map(|messages| match get_json_value(messages, "edges") {
+                Some(edges) => edges
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|edge| edge.get("node").cloned())
+                    .collect(),
+                None => messages.as_array().cloned().unwrap_or_default(),
+            })
+            .ok_or(anyhow::anyhow!("no messages in share page {}", share_code))?;
+
+        Ok(SharedChat {
+            share_code: share_code.to_string(),
+            title: find_json_key(&data, "title")
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_string()),
+            bot: find_json_key(&data, "nickname")
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_string()),
+            messages: messages
+                .into_iter()
+                .filter_map(|message| serde_json::from_value::<SharedMessage>(message).ok())
+                .filter(|message| !message.author.is_empty())
+                .collect(),
+        })
+    }
+
+    /// Share page HTML and its embedded `__NEXT_DATA__` JSON, if any.
+    async fn shared_page(&self, share_code: &str) -> anyhow::Result<(String, Option<Value>)> {
+        let url = format!("{}/s/{}", BASE_URL, share_code);
+        let response = self.client.get(&url).send().await?;
+        let html = response.text().await?;
+
+        let document = Html::parse_document(&html);
+        let selector = Selector::parse("script#__NEXT_DATA__").unwrap();
+        let data = document
+            .select(&selector)
+            .next()
+            .and_then(|script| serde_json::from_str(&script.text().collect::<String>()).ok());
+        Ok((html, data))
     }
 
     pub async fn set_chat_title(&mut self, chat_id: i64, new_title: &str) -> anyhow::Result<bool> {
@@ -339,12 +452,19 @@ impl PoeApi {
             })
             .await?;
         if let Some(data) = get_json_value(&response, "data.chatSetTitle") {
-            let status = get_json_value(data, "status").and_then(|v| v.as_str()).unwrap_or("");
-            if let Some(status_message) = get_json_value(data, "statusMessage").and_then(|v| v.as_str()) {
+            let status = get_json_value(data, "status")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            if let Some(status_message) =
+                get_json_value(data, "statusMessage").and_then(|v| v.as_str())
+            {
                 if !status_message.is_empty() {
                     anyhow::bail!(format!("{}: {}", status, status_message));
                 }
             }
+            if let Some(settings) = self.chat_settings.get_mut(&chat_id) {
+                settings.title = Some(new_title.to_string());
+            }
             return Ok(true);
         }
         Ok(false)
@@ -366,16 +486,26 @@ impl PoeApi {
             })
             .await?;
         let is_success = get_json_value(&response, "data.chatSetContextOptimization").is_some();
+        if is_success {
+            if let Some(settings) = self.chat_settings.get_mut(&chat_id) {
+                settings.context_optimization = Some(enabled);
+            }
+        }
         Ok(is_success)
     }
 
     pub async fn delete_chat(&mut self, chat_id: i64) -> anyhow::Result<bool> {
+        self.delete_chat_paced(chat_id, 0).await
+    }
+
+    async fn delete_chat_paced(&mut self, chat_id: i64, ratelimit: u64) -> anyhow::Result<bool> {
         let response = self
             .send_request(RequestData {
                 query_name: QueryHash::useDeleteChat_deleteChat_Mutation,
                 data: json!({
                     "chatId": chat_id
                 }),
+                ratelimit,
                 ..Default::default()
             })
             .await?;
@@ -383,6 +513,43 @@ impl PoeApi {
         Ok(is_success)
     }
 
+    /// Deletes every chat of the history matching `predicate`. With `dry_run`
+    /// nothing is deleted and the matching chats are only listed.
+    pub async fn delete_chats_where(
+        &mut self,
+        predicate: impl Fn(&Chat) -> bool + Send + Sync,
+        dry_run: bool,
+    ) -> anyhow::Result<Vec<ChatDeletion>> {
+        let mut chats = vec![];
+        let mut history = self.chat_history().retain(&predicate);
+        while let Some(chat) = history.next().await {
+            chats.push(chat);
+        }
+        if !history.is_exhausted() {
+            log::warn!("Chat history ended early, some chats may be left");
+        }
+        drop(history);
+
+        let mut deletions = vec![];
+        for (index, chat) in chats.into_iter().enumerate() {
+            let status = if dry_run {
+                DeletionStatus::DryRun
+            } else {
+                // pace every deletion after the first one
+                match self
+                    .delete_chat_paced(chat.chat_id, index.min(1) as u64)
+                    .await
+                {
+                    Ok(true) => DeletionStatus::Deleted,
+                    Ok(false) => DeletionStatus::Failed("not deleted".to_string()),
+                    Err(err) => DeletionStatus::Failed(err.to_string()),
+                }
+            };
+            deletions.push(ChatDeletion { chat, status });
+        }
+        Ok(deletions)
+    }
+
     pub async fn purge_all_conversations(&mut self) -> bool {
         self.send_request(RequestData {
             query_name:
@@ -420,165 +587,293 @@ impl PoeApi {
     pub async fn send_message(
         &mut self,
         payload: SendMessageData<'_>,
-    ) -> anyhow::Result<MessageContext> {
-        let bot = if payload.bot_handle.is_empty() {
+    ) -> anyhow::Result<MessageContext<'_>> {
+        let files = generate_file(&payload.files).await?;
+        let (bot, request_data) = self
+            .message_request(payload.bot_handle, payload.message, payload.chat_id, files)
+            .await?;
+
+        let file_names = request_data
+            .files
+            .iter()
+            .map(|f| f.name.clone())
+            .collect::<Vec<_>>();
+
+        self.connect_websocket().await?;
+        let response = self.send_request(request_data).await?;
+        let (chat, user_message, bot_message) = parse_message_response(&response, &bot)?;
+        for name in file_names {
+            log::info!("File '{}' uploaded successfully", name);
+        }
+        Ok(MessageContext::new(self, chat, user_message, bot_message).with_bot(bot))
+    }
+
+    /// Account-level events: unread chats, point balance and viewer state.
+    /// Connects the websocket if needed.
+    pub async fn events(&mut self) -> anyhow::Result<AccountEvents<'_>> {
+        if self.stream_reader.is_none() {
+            self.connect_websocket().await?;
+        }
+        Ok(AccountEvents::new(self))
+    }
+
+    /// Sends `prompt` to every bot at once, each in a new chat. The replies
+    /// stream in through the returned [`Comparison`].
+    pub async fn compare(
+        &mut self,
+        prompt: &str,
+        bots: &[&str],
+        files: Vec<FileInput<'_>>,
+    ) -> anyhow::Result<Comparison<'_>> {
+        Comparison::start(self, prompt, bots, files).await
+    }
+
+    /// `SendMessageMutation` request for `bot_handle`, or the default bot when
+    /// empty. Returns the resolved bot with it.
+    pub(crate) async fn message_request(
+        &mut self,
+        bot_handle: &str,
+        message: &str,
+        chat_id: Option<i64>,
+        files: Vec<FileData>,
+    ) -> anyhow::Result<(String, RequestData)> {
+        let bot = if bot_handle.is_empty() {
             let my_settings = self.get_settings().await?;
             my_settings.default_bot.display_name
         } else {
-            payload.bot_handle.to_string()
+            bot_handle.to_string()
         };
 
-        if let Some(bot_info) = self.get_bot_info(&bot).await? {
-            let files = generate_file(&payload.files).await?;
-            let total_size = files.iter().map(|f| f.data.len()).sum::<usize>();
-            if total_size > 350000000 {
-                anyhow::bail!("File size too large. Please try again with a smaller file.");
-            }
-            let attachments = (1..=files.len())
-                .map(|i| format!("file{}", i))
-                .collect::<Vec<_>>();
+        let Some(bot_info) = self.get_bot_info(&bot).await? else {
+            anyhow::bail!(
+                "Failed to get bot info for {}. Make sure the bot exists before creating new chat.",
+                bot
+            )
+        };
 
-            let api_path = if payload.files.is_empty() {
-                RequestPath::GqlPost
-            } else {
-                RequestPath::GqlUploadPost
-            };
+        let total_size = files.iter().map(|f| f.data.len()).sum::<usize>();
+        if total_size > 350000000 {
+            anyhow::bail!("File size too large. Please try again with a smaller file.");
+        }
+        let attachments = (1..=files.len())
+            .map(|i| format!("file{}", i))
+            .collect::<Vec<_>>();
 
-            self.connect_websocket().await?;
-            let mut data = json!({
-                    "chatId": null,
-                    "bot": bot,
-                    "query": payload.message,
-                    "shouldFetchChat": true,
-                    "source": {
-                        "sourceType": "chat_input",
-                        "chatInputMetadata": {
-                            "useVoiceRecord": false,
-                        },
+        let api_path = if files.is_empty() {
+            RequestPath::GqlPost
+        } else {
+            RequestPath::GqlUploadPost
+        };
+
+        let data = json!({
+                "chatId": chat_id,
+                "bot": bot,
+                "query": message,
+                "shouldFetchChat": true,
+                "source": {
+                    "sourceType": "chat_input",
+                    "chatInputMetadata": {
+                        "useVoiceRecord": false,
                     },
-                    "clientNonce": generate_nonce(16),
-                    "sdid": "",
-                    "attachments": attachments,
-                    "existingMessageAttachmentsIds": [],
-                    "messagePointsDisplayPrice": bot_info.display_message_point_price,
-            });
-
-            if let Some(chat_id_val) = payload.chat_id {
-                if let Some(chat_id) = data.get_mut("chatId") {
-                    *chat_id = json!(chat_id_val);
+                },
+                "clientNonce": generate_nonce(16),
+                "sdid": "",
+                "attachments": attachments,
+                "existingMessageAttachmentsIds": [],
+                "messagePointsDisplayPrice": bot_info.display_message_point_price,
+        });
+
+        Ok((
+            bot,
+            RequestData {
+                path: api_path,
+                query_name: QueryHash::SendMessageMutation,
+                data,
+                files,
+                ..Default::default()
+            },
+        ))
+    }
+
+    /// Sends a message and waits for the complete reply instead of streaming it.
+    pub async fn send_and_wait(&mut self, payload: SendMessageData<'_>) -> anyhow::Result<Reply> {
+        self.send_message(payload).await?.reply().await
+    }
+
+    pub(crate) fn chat_page_request(chat_code: &str) -> RequestData {
+        RequestData {
+            query_name: QueryHash::ChatPageQuery,
+            data: json!({
+               "chatCode": chat_code
+            }),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) async fn chat_page(&mut self, chat_code: &str) -> anyhow::Result<Value> {
+        let response = self
+            .send_request(Self::chat_page_request(chat_code))
+            .await?;
+
+        match get_json_value(&response, "data.chatOfCode") {
+            Some(data) if !data.is_null() => Ok(data.clone()),
+            _ => anyhow::bail!("Chat {chat_code} not found"),
+        }
+    }
+
+    /// Starts a new chat with `bot` from the messages of `chat` up to
+    /// `message_id` (since the last chat break), and records the fork in `forks`.
+    pub async fn fork_chat(
+        &mut self,
+        chat: &Chat,
+        message_id: i64,
+        bot: &str,
+        mode: ForkMode,
+    ) -> anyhow::Result<Chat> {
+        let mut transcript = vec![];
+        let mut is_found = false;
+        let messages = ChatContext::new(self, chat.clone()).messages().await?;
+        for message in messages.into_iter().rev() {
+            if message.is_chat_break() {
+                if is_found {
+                    break;
                 }
+                continue;
             }
-
-            let response = self
-                .send_request(RequestData {
-                    path: api_path.clone(),
-                    query_name: QueryHash::SendMessageMutation,
-                    data,
-                    files: files.clone(),
+            is_found |= message.message_id == message_id;
+            if is_found {
+                transcript.push(message);
+            }
+        }
+        if !is_found {
+            anyhow::bail!(
+                "Message {} not found in the latest messages of chat {}",
+                message_id,
+                chat.chat_code
+            );
+        }
+        transcript.reverse();
+
+        let child = match mode {
+            ForkMode::Replay => {
+                let text = transcript
+                    .iter()
+                    .map(|m| {
+                        let author = if m.is_human() { "User" } else { "Assistant" };
+                        format!("{}: {}", author, m.text.trim())
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                self.send_and_wait(SendMessageData {
+                    bot_handle: bot,
+                    message: &FORK_REPLAY_PROMPT.replace("{transcript}", &text),
                     ..Default::default()
                 })
-                .await?;
-
-            if get_json_value(&response, "data").is_none() && get_json_value(&response, "errors").is_some() {
-                anyhow::bail!(
-                    "Bot {} not found. Make sure the bot exists before creating new chat.",
-                    bot
-                )
+                .await?
+                .chat
             }
-            if let Some(data) = get_json_value(&response, "data.messageEdgeCreate")
-            {
-                let message_data = serde_json::from_value::<MessageEdgeCreate>(data.clone())?;
+            ForkMode::ShareImport => {
+                let message_ids = transcript.iter().map(|m| m.message_id).collect::<Vec<_>>();
+                let url = self
+                    .get_message_share_url(chat.chat_id, &message_ids)
+                    .await?;
+                self.continue_shared_chat(parse_share_code(&url), bot)
+                    .await?
+            }
+        };
 
-                if message_data.status == "success" {
-                    for file in files.iter() {
-                        log::info!("File '{}' uploaded successfully", file.name);
-                    }
+        self.forks.record(ChatFork {
+            parent: chat.clone(),
+            message_id,
+            child: child.clone(),
+            bot: bot.to_string(),
+            mode,
+        });
+        Ok(child)
+    }
+
+    /// Fetches the settings of a chat and caches them in `chat_settings`.
+    pub async fn get_chat_settings(&mut self, chat_code: &str) -> anyhow::Result<ChatSettings> {
+        let data = self.chat_page(chat_code).await?;
+        let settings = ChatSettings::from_chat_page(&data);
+        self.chat_settings
+            .insert(settings.chat_id, settings.clone());
+        Ok(settings)
+    }
 
-                    if let (Some(chat), Some(user_message), Some(bot_message)) = (
-                        message_data.chat,
-                        message_data.message,
-                        message_data.bot_message,
-                    ) {
-                        return Ok(MessageContext::new(self, chat, user_message, bot_message));
+    /// Marks a chat as read. Poe marks a chat read when it is opened, and this
+    /// opens it through `ChatPageQuery` like the web app does; no dedicated
+    /// mutation is known. A chat missing from `chat_settings` is looked up in
+    /// the chat history, most recently active first. Fails if the chat does
+    /// not exist.
+    pub async fn mark_chat_read(&mut self, chat_id: i64) -> anyhow::Result<()> {
+        let chat_code = match self.chat_settings.get(&chat_id) {
+            Some(settings) => settings.chat_code.clone(),
+            None => {
+                let mut history = self.chat_history();
+                let mut chat_code = None;
+                while let Some(chat) = history.next().await {
+                    if chat.chat_id == chat_id {
+                        chat_code = Some(chat.chat_code);
+                        break;
                     }
                 }
-                if !message_data.status_message.is_empty() {
-                    anyhow::bail!("{}: {}", message_data.status, message_data.status_message)
-                }
+                chat_code.ok_or(anyhow::anyhow!("Chat {} not found", chat_id))?
             }
-        }
+        };
+        self.get_chat_settings(&chat_code).await?;
+        Ok(())
+    }
 
-        anyhow::bail!(
-            "Failed to get bot info for {}. Make sure the bot exists before creating new chat.",
-            bot
-        )
+    /// Chat metadata and its latest messages.
+    pub async fn get_chat(&mut self, chat_code: &str) -> anyhow::Result<ChatPage> {
+        let data = self.chat_page(chat_code).await?;
+        Ok(ChatPage {
+            chat: serde_json::from_value::<Chat>(data.clone())?,
+            messages: parse_messages(&data),
+        })
     }
 
-    pub async fn retry_message(&mut self, chat_code: &str) -> anyhow::Result<MessageContext> {
-        let response = self
-            .send_request(RequestData {
-                query_name: QueryHash::ChatPageQuery,
-                data: json!({
-                   "chatCode": chat_code
-                }),
-                ..Default::default()
-            })
-            .await?;
+    pub async fn retry_message(&mut self, chat_code: &str) -> anyhow::Result<MessageContext<'_>> {
+        let data = self.chat_page(chat_code).await?;
+        let msg_price = get_json_value(
+            &data,
+            "defaultBotObject.messagePointLimit.displayMessagePointPrice",
+        )
+        .and_then(|v| v.as_i64());
+        let messages = parse_messages(&data);
 
-        if let Some(data) = get_json_value(&response, "data.chatOfCode") {
-            let msg_price = get_json_value(data, "defaultBotObject.messagePointLimit.displayMessagePointPrice")
-                .and_then(|v| v.as_i64());
-            let edges_map = get_json_value(data, "messagesConnection.edges")
-                .and_then(|v| v.as_array())
-                .map(|arr| {
-                    arr.iter()
-                        .filter_map(|value| {
-                            if let Some(node) = get_json_value(value, "node") {
-                                if let Ok(message) = serde_json::from_value::<Message>(node.clone())
-                                {
-                                    return Some(message);
-                                }
-                            }
-                            None
-                        })
-                        .collect::<Vec<_>>()
-                });
-
-            if let (Some(display_message_point_price), Some(messages)) = (msg_price, edges_map) {
-                let [user_message, bot_message] = &messages[messages.len() - 2..] else {
-                    panic!("Vector does not have the expected number of elements");
-                };
-
-                let chat = serde_json::from_value::<Chat>(data.clone())?;
-
-                self.connect_websocket().await?;
-                let response = self
-                    .send_request(RequestData {
-                        query_name: QueryHash::regenerateMessageMutation,
-                        data: json!({
-                            "messageId": bot_message.message_id,
-                            "messagePointsDisplayPrice": display_message_point_price
-                        }),
-                        ..Default::default()
-                    })
-                    .await?;
+        if let (Some(display_message_point_price), [.., user_message, bot_message]) =
+            (msg_price, messages.as_slice())
+        {
+            let chat = serde_json::from_value::<Chat>(data.clone())?;
 
-                if let Some(data) = get_json_value(&response, "data.messageRegenerate")
-                {
-                    if let (Some(status), Some(status_message)) = (
-                        get_json_value(data, "status").and_then(|v| v.as_str()),
-                        get_json_value(data, "statusMessage").and_then(|v| v.as_str()),
-                    ) {
-                        if !status_message.is_empty() {
-                            anyhow::bail!("{}: {}", status, status_message)
-                        }
-                        return Ok(MessageContext::new(
-                            self,
-                            chat,
-                            user_message.clone(),
-                            bot_message.clone(),
-                        ));
+            self.connect_websocket().await?;
+            let response = self
+                .send_request(RequestData {
+                    query_name: QueryHash::regenerateMessageMutation,
+                    data: json!({
+                        "messageId": bot_message.message_id,
+                        "messagePointsDisplayPrice": display_message_point_price
+                    }),
+                    ..Default::default()
+                })
+                .await?;
+
+            if let Some(data) = get_json_value(&response, "data.messageRegenerate") {
+                if let (Some(status), Some(status_message)) = (
+                    get_json_value(data, "status").and_then(|v| v.as_str()),
+                    get_json_value(data, "statusMessage").and_then(|v| v.as_str()),
+                ) {
+                    if !status_message.is_empty() {
+                        anyhow::bail!("{}: {}", status, status_message)
                     }
+                    return Ok(MessageContext::new(
+                        self,
+                        chat,
+                        user_message.clone(),
+                        bot_message.clone(),
+                    ));
                 }
             }
         }
@@ -586,15 +881,45 @@ impl PoeApi {
         anyhow::bail!("Failed to retry message of Thread {chat_code}")
     }
 
+    /// Re-attaches to a bot message that may still be generating, e.g. after a
+    /// restart. The stream starts with the text produced so far.
+    pub async fn attach(
+        &mut self,
+        chat_code: &str,
+        message_id: i64,
+    ) -> anyhow::Result<MessageContext<'_>> {
+        // subscribe first, so no update is missed while the current text is fetched
+        self.connect_websocket().await?;
+        let data = self.chat_page(chat_code).await?;
+        let chat = serde_json::from_value::<Chat>(data.clone())?;
+        let messages = parse_messages(&data);
+
+        let Some(index) = messages.iter().position(|m| m.message_id == message_id) else {
+            anyhow::bail!("Message {message_id} not found in Thread {chat_code}")
+        };
+        let Some(user_message) = messages[..index].iter().rev().find(|m| m.is_human()) else {
+            anyhow::bail!("Failed to find the user message of {message_id} in Thread {chat_code}")
+        };
+
+        Ok(
+            MessageContext::new(self, chat, user_message.clone(), messages[index].clone())
+                .attached(),
+        )
+    }
+
+    pub(crate) fn cancel_message_request(chat_id: i64) -> RequestData {
+        RequestData {
+            query_name: QueryHash::cancelViewerActiveJobs_cancelViewerActiveJobs_Mutation,
+            data: json!({
+                "chatId": chat_id
+            }),
+            ..Default::default()
+        }
+    }
+
     pub async fn cancel_message(&mut self, chat_id: i64) -> anyhow::Result<bool> {
         let response = self
-            .send_request(RequestData {
-                query_name: QueryHash::cancelViewerActiveJobs_cancelViewerActiveJobs_Mutation,
-                data: json!({
-                    "chatId": chat_id
-                }),
-                ..Default::default()
-            })
+            .send_request(Self::cancel_message_request(chat_id))
             .await?;
 
         let is_success = get_json_value(&response, "data.cancelViewerActiveJobs").is_some();
@@ -637,9 +962,12 @@ impl PoeApi {
                 ..Default::default()
             })
             .await?;
-        let point = get_json_value(&response, "data.messageOfCode.responsibleJob.totalCostPoints")
-            .and_then(|v| v.as_i64())
-            .unwrap_or(-1);
+        let point = get_json_value(
+            &response,
+            "data.messageOfCode.responsibleJob.totalCostPoints",
+        )
+        .and_then(|v| v.as_i64())
+        .unwrap_or(-1);
         Ok(point)
     }
 
@@ -658,8 +986,8 @@ impl PoeApi {
                 ..Default::default()
             })
             .await?;
-        if let Some(share_code) = get_json_value(&response, "data.messagesShare.shareCode")
-            .and_then(|c| c.as_str())
+        if let Some(share_code) =
+            get_json_value(&response, "data.messagesShare.shareCode").and_then(|c| c.as_str())
         {
             let url = format!("{}/s/{}", BASE_URL, share_code);
             return Ok(url);
@@ -681,8 +1009,11 @@ impl PoeApi {
                 })
                 .await?;
 
-            if let Some(share_url) = get_json_value(&response, "data.sharePreviewFromMessage.sharedPreview.shareUrl")
-                .and_then(|v| v.as_str())
+            if let Some(share_url) = get_json_value(
+                &response,
+                "data.sharePreviewFromMessage.sharedPreview.shareUrl",
+            )
+            .and_then(|v| v.as_str())
             {
                 share_urls.push(share_url.to_string());
                 continue;
@@ -726,11 +1057,13 @@ impl PoeApi {
                 ..Default::default()
             })
             .await?;
-        if let Some(category_names) = get_json_value(&response, "data.exploreBotsCategoryObjects")
-            .and_then(|v| v.as_array())
+        if let Some(category_names) =
+            get_json_value(&response, "data.exploreBotsCategoryObjects").and_then(|v| v.as_array())
         {
             for category in category_names {
-                if let Some(name) = get_json_value(category, "categoryName").and_then(|v| v.as_str()) {
+                if let Some(name) =
+                    get_json_value(category, "categoryName").and_then(|v| v.as_str())
+                {
                     categories.push(name.to_string())
                 }
             }
@@ -813,6 +1146,10 @@ impl PoeApi {
      * +------+
      */
 
+    pub async fn has_unread_chats(&mut self) -> anyhow::Result<bool> {
+        Ok(self.get_settings().await?.has_unread_message)
+    }
+
     pub async fn get_settings(&mut self) -> anyhow::Result<MySettings> {
         let response = self
             .send_request(RequestData {
@@ -828,3 +1165,72 @@ impl PoeApi {
         anyhow::bail!("Failed to fetch settings")
     }
 }
+
+/// Sends a request prepared by [`PoeApi::build_request`]. Kept apart from
+/// `PoeApi` so it can run without borrowing the client (e.g. in a spawned task).
+pub(crate) async fn execute_request(request: RequestBuilder) -> anyhow::Result<Value> {
+    let response = request.send().await?;
+    let data = response.json::<Value>().await?;
+
+    let is_success = data
+        .get("success")
+        .is_some_and(|v| v.as_bool().unwrap_or(false));
+    if !is_success || data.get("data").is_none() {
+        if let Some(err) = get_json_value(&data, "errors.0.message") {
+            let msg = Value::as_str(err).unwrap_or("");
+            if msg == "Server Error" {
+                anyhow::bail!(
+                    "Server Error. Raw response data: {:?}",
+                    serde_json::to_string(&data)?
+                );
+            } else {
+                anyhow::bail!("{}", msg);
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+/// Chat, user message and bot message created by `SendMessageMutation`.
+pub(crate) fn parse_message_response(
+    response: &Value,
+    bot: &str,
+) -> anyhow::Result<(Chat, Message, Message)> {
+    if get_json_value(response, "data").is_none() && get_json_value(response, "errors").is_some() {
+        anyhow::bail!(
+            "Bot {} not found. Make sure the bot exists before creating new chat.",
+            bot
+        )
+    }
+    if let Some(data) = get_json_value(response, "data.messageEdgeCreate") {
+        let message_data = serde_json::from_value::<MessageEdgeCreate>(data.clone())?;
+
+        if message_data.status == "success" {
+            if let (Some(chat), Some(user_message), Some(bot_message)) = (
+                message_data.chat,
+                message_data.message,
+                message_data.bot_message,
+            ) {
+                return Ok((chat, user_message, bot_message));
+            }
+        }
+        if !message_data.status_message.is_empty() {
+            anyhow::bail!("{}: {}", message_data.status, message_data.status_message)
+        }
+    }
+    anyhow::bail!("Failed to send message to {}", bot)
+}
+
+pub(crate) fn parse_messages(chat_data: &Value) -> Vec<Message> {
+    get_json_value(chat_data, "messagesConnection.edges")
+        .and_then(|v| v.as_array())
+        .map(|edges| {
+            edges
+                .iter()
+                .filter_map(|edge| get_json_value(edge, "node"))
+                .filter_map(|node| serde_json::from_value::<Message>(node.clone()).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
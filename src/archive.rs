@@ -0,0 +1,302 @@
+use std::{collections::HashSet, path::Path};
+
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::PoeApi,
+    chat::{Chat, ChatContext},
+    models::message::Message,
+};
+
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS chats (
+    chat_id INTEGER PRIMARY KEY,
+    id TEXT NOT NULL,
+    chat_code TEXT NOT NULL UNIQUE,
+    title TEXT,
+    bot TEXT,
+    -- `lastInteractionTime` of the chat when it was synced, in microseconds
+    synced_at INTEGER NOT NULL,
+    -- microseconds, like every time in the archive
+    deleted_at INTEGER
+);
+CREATE TABLE IF NOT EXISTS messages (
+    message_id INTEGER PRIMARY KEY,
+    chat_id INTEGER NOT NULL REFERENCES chats(chat_id),
+    message_code TEXT NOT NULL,
+    author TEXT NOT NULL,
+    text TEXT NOT NULL,
+    state TEXT NOT NULL,
+    creation_time INTEGER NOT NULL
+);
+-- tombstones of older archives were stored in seconds
+UPDATE chats SET deleted_at = deleted_at * 1000000 WHERE deleted_at < 100000000000;
+CREATE INDEX IF NOT EXISTS messages_chat ON messages(chat_id, creation_time);
+CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+    text, content='messages', content_rowid='message_id'
+);
+CREATE TRIGGER IF NOT EXISTS messages_ai AFTER INSERT ON messages BEGIN
+    INSERT INTO messages_fts(rowid, text) VALUES (new.message_id, new.text);
+END;
+CREATE TRIGGER IF NOT EXISTS messages_ad AFTER DELETE ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, text) VALUES ('delete', old.message_id, old.text);
+END;
+CREATE TRIGGER IF NOT EXISTS messages_au AFTER UPDATE ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, text) VALUES ('delete', old.message_id, old.text);
+    INSERT INTO messages_fts(rowid, text) VALUES (new.message_id, new.text);
+END;
+"#;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncReport {
+    pub chats: usize,
+    /// Chats with no activity since their last sync.
+    pub unchanged: usize,
+    pub messages: usize,
+    pub deleted: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveHit {
+    pub chat_code: String,
+    pub title: Option<String>,
+    pub bot: Option<String>,
+    pub message_id: i64,
+    pub author: String,
+    /// Matched text with the hits wrapped in `[` and `]`.
+    pub snippet: String,
+    pub created_at: DateTime<Utc>,
+    pub is_deleted: bool,
+}
+
+/// Local SQLite mirror of the chat history, with full-text search over messages.
+pub struct Archive {
+    conn: Connection,
+}
+
+impl Archive {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Mirrors new chats and messages. Chats with no activity since their last
+    /// sync are skipped, only messages newer than the ones already archived are
+    /// stored, and chats gone from the history are tombstoned.
+    pub async fn sync(&mut self, api: &mut PoeApi) -> anyhow::Result<SyncReport> {
+        let mut report = SyncReport::default();
+
+        let mut chats = vec![];
+        let mut history = api.chat_history();
+        while let Some(chat) = history.next().await {
+            chats.push(chat);
+        }
+        let is_complete = history.is_exhausted();
+        drop(history);
+
+        for chat in &chats {
+            if !self.is_stale(chat)? {
+                report.unchanged += 1;
+                continue;
+            }
+            report.messages += self.sync_chat(api, chat).await?;
+            report.chats += 1;
+        }
+
+        // a partial history says nothing about deleted chats
+        if is_complete {
+            report.deleted = self.tombstone(&chats)?;
+        } else {
+            log::warn!("Chat history ended early, skipping deletion check");
+        }
+        Ok(report)
+    }
+
+    async fn sync_chat(&mut self, api: &mut PoeApi, chat: &Chat) -> anyhow::Result<usize> {
+        let last_time = self.last_message_time(chat.chat_id)?;
+        let messages = match ChatContext::new(api, chat.clone()).messages().await {
+            Ok(messages) => messages,
+            Err(err) => {
+                log::warn!("Failed to read chat {}, skipped: {}", chat.chat_code, err);
+                return Ok(0);
+            }
+        };
+        let mut new_messages: Vec<Message> = vec![];
+        for message in messages.into_iter().rev() {
+            // newest first: once an archived, finished message shows up the rest is known
+            if last_time.is_some_and(|time| message.creation_time <= time)
+                && self.is_archived(message.message_id)?
+            {
+                break;
+            }
+            new_messages.push(message);
+        }
+
+        let bot = match chat.bot.clone().or(self.bot_of(chat.chat_id)?) {
+            Some(bot) => Some(bot),
+            None => new_messages
+                .iter()
+                .find(|message| !message.is_human() && !message.is_chat_break())
+                .map(|message| message.author.clone()),
+        };
+        let synced_at = chat
+            .last_interaction_time
+            .unwrap_or_else(|| Utc::now().timestamp_micros());
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO chats (chat_id, id, chat_code, title, bot, synced_at, deleted_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, NULL)
+             ON CONFLICT(chat_id) DO UPDATE SET
+                title = excluded.title, bot = excluded.bot,
+                synced_at = excluded.synced_at, deleted_at = NULL",
+            params![
+                chat.chat_id,
+                chat.id,
+                chat.chat_code,
+                chat.title,
+                bot,
+                synced_at
+            ],
+        )?;
+        for message in &new_messages {
+            tx.execute(
+                "INSERT INTO messages (message_id, chat_id, message_code, author, text, state, creation_time)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                 ON CONFLICT(message_id) DO UPDATE SET
+                    text = excluded.text, state = excluded.state",
+                params![
+                    message.message_id,
+                    chat.chat_id,
+                    message.message_code,
+                    message.author,
+                    message.text,
+                    message.state,
+                    message.creation_time
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(new_messages.len())
+    }
+
+    fn tombstone(&mut self, chats: &[Chat]) -> anyhow::Result<usize> {
+        let alive = chats
+            .iter()
+            .map(|chat| chat.chat_id)
+            .collect::<HashSet<_>>();
+        let mut stmt = self
+            .conn
+            .prepare("SELECT chat_id FROM chats WHERE deleted_at IS NULL")?;
+        let stored = stmt
+            .query_map([], |row| row.get::<_, i64>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        drop(stmt);
+
+        let now = Utc::now().timestamp_micros();
+        let mut deleted = 0;
+        for chat_id in stored.into_iter().filter(|id| !alive.contains(id)) {
+            deleted += self.conn.execute(
+                "UPDATE chats SET deleted_at = ?1 WHERE chat_id = ?2",
+                params![now, chat_id],
+            )?;
+        }
+        Ok(deleted)
+    }
+
+    /// Whether the chat saw activity since its last sync, or still has a bot
+    /// message being written. Chats without a `lastInteractionTime` are always
+    /// synced.
+    fn is_stale(&self, chat: &Chat) -> anyhow::Result<bool> {
+        let Some(last_interaction_time) = chat.last_interaction_time else {
+            return Ok(true);
+        };
+        let is_writing: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM messages
+             WHERE chat_id = ?1 AND state IN ('incomplete', 'pending'))",
+            [chat.chat_id],
+            |row| row.get(0),
+        )?;
+        if is_writing {
+            return Ok(true);
+        }
+        let synced_at: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT synced_at FROM chats WHERE chat_id = ?1 AND deleted_at IS NULL",
+                [chat.chat_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(synced_at.is_none_or(|synced_at| last_interaction_time > synced_at))
+    }
+
+    fn bot_of(&self, chat_id: i64) -> anyhow::Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT bot FROM chats WHERE chat_id = ?1",
+                [chat_id],
+                |row| row.get::<_, Option<String>>(0),
+            )
+            .optional()?
+            .flatten())
+    }
+
+    fn last_message_time(&self, chat_id: i64) -> anyhow::Result<Option<i64>> {
+        Ok(self.conn.query_row(
+            "SELECT MAX(creation_time) FROM messages WHERE chat_id = ?1",
+            [chat_id],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// A bot message still being written when it was archived is fetched again.
+    fn is_archived(&self, message_id: i64) -> anyhow::Result<bool> {
+        let state: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT state FROM messages WHERE message_id = ?1",
+                [message_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(state.is_some_and(|state| !matches!(state.as_str(), "incomplete" | "pending")))
+    }
+
+    /// Full-text search over archived messages, best match first. `query` uses
+    /// the SQLite FTS5 syntax (`rust AND async`, `"exact phrase"`, `pref*`).
+    pub fn search(&self, query: &str, limit: usize) -> anyhow::Result<Vec<ArchiveHit>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT c.chat_code, c.title, c.bot, m.message_id, m.author,
+                    snippet(messages_fts, 0, '[', ']', '...', 16),
+                    m.creation_time, c.deleted_at IS NOT NULL
+             FROM messages_fts
+             JOIN messages m ON m.message_id = messages_fts.rowid
+             JOIN chats c ON c.chat_id = m.chat_id
+             WHERE messages_fts MATCH ?1
+             ORDER BY rank
+             LIMIT ?2",
+        )?;
+        let hits = stmt
+            .query_map(params![query, limit as i64], |row| {
+                Ok(ArchiveHit {
+                    chat_code: row.get(0)?,
+                    title: row.get(1)?,
+                    bot: row.get(2)?,
+                    message_id: row.get(3)?,
+                    author: row.get(4)?,
+                    snippet: row.get(5)?,
+                    created_at: DateTime::from_timestamp_micros(row.get(6)?).unwrap_or_default(),
+                    is_deleted: row.get(7)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(hits)
+    }
+}
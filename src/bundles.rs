@@ -10,6 +10,12 @@ use crate::{
     models::Token,
 };
 
+struct ScriptElement {
+    src: Option<String>,
+    script_type: Option<String>,
+    text: String,
+}
+
 #[derive(Default)]
 pub struct PoeBundle {
     client: Client,
@@ -51,10 +57,20 @@ impl PoeBundle {
 
         let response = self.client.get(BASE_URL).send().await?;
         let document = response.text().await?;
-        let html = Html::parse_document(&document);
-        let selector = Selector::parse("script").unwrap();
-
-        for script_element in html.select(&selector) {
+        // collected first, the parsed document must not be held across an await
+        let scripts = {
+            let html = Html::parse_document(&document);
+            let selector = Selector::parse("script").unwrap();
+            html.select(&selector)
+                .map(|element| ScriptElement {
+                    src: element.attr("src").map(String::from),
+                    script_type: element.attr("type").map(String::from),
+                    text: element.text().next().unwrap_or_default().to_string(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for script_element in scripts {
             self.process_script_element(script_element).await?;
         }
 
@@ -63,10 +79,10 @@ impl PoeBundle {
 
     async fn process_script_element(
         &mut self,
-        script_element: scraper::element_ref::ElementRef<'_>,
+        script_element: ScriptElement,
     ) -> anyhow::Result<()> {
         // Periksa apakah element script memiliki atribut src
-        if let Some(src) = script_element.attr("src").map(String::from) {
+        if let Some(src) = script_element.src {
             // Jika sudah pernah diproses, lewati
             if self.src_scripts.contains(&src) {
                 return Ok(());
@@ -85,19 +101,19 @@ impl PoeBundle {
             }
         } else {
             // Mengolah inline script
-            let script_text = script_element.text().next().unwrap_or_default();
+            let script_text = script_element.text;
             if script_text.is_empty()
                 || script_text.contains("document.")
                 || !script_text.contains("function")
             {
                 return Ok(());
             }
-            if let Some(script_type) = script_element.attr("type") {
+            if let Some(script_type) = script_element.script_type {
                 if script_type == "application/json" {
                     return Ok(());
                 }
             }
-            self.window.push_str(script_text);
+            self.window.push_str(&script_text);
         }
 
         Ok(())
@@ -1,6 +1,28 @@
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 
-use crate::{api::PoeApi, message::MessageContext, models::SendMessageData};
+use crate::{
+    api::PoeApi,
+    fork::ForkMode,
+    message::MessageContext,
+    models::{message::Message, SendMessageData},
+    utils::get_json_value,
+};
+
+fn get_handle<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: Value = Deserialize::deserialize(deserializer)?;
+    Ok(match value {
+        Value::String(handle) => Some(handle),
+        value => value
+            .get("handle")
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string()),
+    })
+}
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -9,6 +31,168 @@ pub struct Chat {
     pub chat_id: i64,
     pub chat_code: String,
     pub title: Option<String>,
+    /// Handle of the chat's default bot.
+    #[serde(
+        default,
+        rename(deserialize = "defaultBotObject"),
+        alias = "bot",
+        deserialize_with = "get_handle"
+    )]
+    pub bot: Option<String>,
+    /// Microseconds since the epoch.
+    #[serde(default)]
+    pub creation_time: Option<i64>,
+    /// Microseconds since the epoch.
+    #[serde(default)]
+    pub last_interaction_time: Option<i64>,
+}
+
+impl Chat {
+    pub fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.creation_time.and_then(DateTime::from_timestamp_micros)
+    }
+
+    pub fn last_active_at(&self) -> Option<DateTime<Utc>> {
+        self.last_interaction_time
+            .and_then(DateTime::from_timestamp_micros)
+    }
+}
+
+/// A chat with its latest messages, oldest first.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatPage {
+    pub chat: Chat,
+    pub messages: Vec<Message>,
+}
+
+/// Current settings of a chat, read from `ChatPageQuery` and kept up to date
+/// with `chatSettingsUpdated` events.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatSettings {
+    pub chat_id: i64,
+    pub chat_code: String,
+    pub title: Option<String>,
+    pub default_bot: Option<String>,
+    pub context_optimization: Option<bool>,
+    /// Per-chat point threshold, `None` when the account default applies.
+    pub point_threshold: Option<i64>,
+    /// Handles of the bots taking part in the chat.
+    pub member_bots: Vec<String>,
+    /// Microseconds since the epoch.
+    pub creation_time: Option<i64>,
+}
+
+impl ChatSettings {
+    pub fn from_chat_page(data: &Value) -> Self {
+        let mut settings = Self::default();
+        settings.update(data);
+        settings
+    }
+
+    /// Applies the fields present in `data`, a chat object or a
+    /// `chatSettingsUpdated` payload.
+    pub fn update(&mut self, data: &Value) {
+        if let Some(chat_id) = data.get("chatId").and_then(|v| v.as_i64()) {
+            self.chat_id = chat_id;
+        }
+        if let Some(chat_code) = data.get("chatCode").and_then(|v| v.as_str()) {
+            self.chat_code = chat_code.to_string();
+        }
+        if let Some(title) = data.get("title") {
+            self.title = title.as_str().map(|v| v.to_string());
+        }
+        if let Some(bot) = data.get("defaultBotObject") {
+            self.default_bot = get_handle(bot.clone()).ok().flatten();
+        }
+        if let Some(enabled) = data.get("isContextOptimizationOn") {
+            self.context_optimization = enabled.as_bool();
+        }
+        if let Some(points) = data.get("messagePointPriceThreshold") {
+            self.point_threshold = match points {
+                Value::String(points) => points.parse().ok(),
+                points => points.as_i64(),
+            };
+        }
+        if let Some(members) = data.get("membersConnection") {
+            self.member_bots = members
+                .get("edges")
+                .and_then(|v| v.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|edge| get_json_value(edge, "node.bot.handle"))
+                .filter_map(|v| v.as_str())
+                .map(|v| v.to_string())
+                .collect();
+        }
+        if let Some(time) = data.get("creationTime").and_then(|v| v.as_i64()) {
+            self.creation_time = Some(time);
+        }
+    }
+
+    /// Whether `handle` is the default bot or a member bot (case-insensitive).
+    pub fn is_member(&self, handle: &str) -> bool {
+        self.default_bot
+            .iter()
+            .chain(&self.member_bots)
+            .any(|bot| bot.eq_ignore_ascii_case(handle))
+    }
+
+    /// Records a member bot. Returns `false` if it already was one.
+    pub fn add_member(&mut self, handle: &str) -> bool {
+        if self.is_member(handle) {
+            return false;
+        }
+        self.member_bots.push(handle.to_string());
+        true
+    }
+
+    pub fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.creation_time.and_then(DateTime::from_timestamp_micros)
+    }
+}
+
+/// A message of a shared chat, as shown on its share page.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SharedMessage {
+    pub author: String,
+    pub text: String,
+    /// Microseconds since the epoch.
+    pub creation_time: Option<i64>,
+}
+
+impl SharedMessage {
+    pub fn is_human(&self) -> bool {
+        self.author == "human"
+    }
+}
+
+/// A shared chat read without importing it, see [`PoeApi::preview_shared_chat`].
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedChat {
+    pub share_code: String,
+    pub title: Option<String>,
+    pub bot: Option<String>,
+    pub messages: Vec<SharedMessage>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(tag = "type", content = "reason", rename_all = "camelCase")]
+pub enum DeletionStatus {
+    /// Would have been deleted.
+    DryRun,
+    Deleted,
+    Failed(String),
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatDeletion {
+    pub chat: Chat,
+    pub status: DeletionStatus,
 }
 
 pub struct ChatContext<'a> {
@@ -24,11 +208,77 @@ impl<'a> ChatContext<'a> {
     pub async fn send_message(
         &mut self,
         mut payload: SendMessageData<'_>,
-    ) -> anyhow::Result<MessageContext> {
+    ) -> anyhow::Result<MessageContext<'_>> {
         payload.chat_id = Some(self.inner.chat_id);
         self.api.send_message(payload).await
     }
 
+    /// Latest messages of the chat, oldest first. This is the single page
+    /// `ChatPageQuery` returns: older messages need a paginated messages query,
+    /// which the crate does not know yet.
+    pub async fn messages(&mut self) -> anyhow::Result<Vec<Message>> {
+        Ok(self.api.get_chat(&self.inner.chat_code).await?.messages)
+    }
+
+    /// Continues this chat up to `message_id` in a new chat with `bot`. The fork
+    /// is recorded in `PoeApi::forks`.
+    pub async fn fork(
+        &mut self,
+        message_id: i64,
+        bot: &str,
+        mode: ForkMode,
+    ) -> anyhow::Result<ChatContext<'_>> {
+        let child = self
+            .api
+            .fork_chat(&self.inner, message_id, bot, mode)
+            .await?;
+        Ok(ChatContext::new(self.api, child))
+    }
+
+    /// Sends a message to one bot of a group chat. A bot that is not a member
+    /// yet joins the chat with this message.
+    pub async fn send_to(
+        &mut self,
+        member: &str,
+        mut payload: SendMessageData<'_>,
+    ) -> anyhow::Result<MessageContext<'_>> {
+        if let Some(settings) = self.api.chat_settings.get_mut(&self.inner.chat_id) {
+            settings.add_member(member);
+        }
+        payload.bot_handle = member;
+        self.send_message(payload).await
+    }
+
+    /// Adds a bot to the chat. Poe has no separate call for this: a bot joins
+    /// a chat with the first message sent to it there, see
+    /// [`ChatContext::send_to`]. Until then the member is only known locally,
+    /// and [`ChatContext::refresh`] drops it, which does not stop `send_to`.
+    /// Returns `false` if the bot already is a member.
+    pub async fn add_bot(&mut self, handle: &str) -> anyhow::Result<bool> {
+        if self.api.get_bot_info(handle).await?.is_none() {
+            anyhow::bail!("Bot {} not found", handle);
+        }
+        let mut settings = self.settings().await?;
+        let is_added = settings.add_member(handle);
+        self.api.chat_settings.insert(self.inner.chat_id, settings);
+        Ok(is_added)
+    }
+
+    /// Bots taking part in the chat, the default bot first.
+    pub async fn members(&mut self) -> anyhow::Result<Vec<String>> {
+        let settings = self.settings().await?;
+        let mut members = settings.default_bot.into_iter().collect::<Vec<_>>();
+        for bot in settings.member_bots {
+            if !members
+                .iter()
+                .any(|member| member.eq_ignore_ascii_case(&bot))
+            {
+                members.push(bot);
+            }
+        }
+        Ok(members)
+    }
+
     pub async fn clear_context(&mut self) -> anyhow::Result<bool> {
         self.api.clear_chat_context(self.inner.chat_id).await
     }
@@ -46,6 +296,36 @@ impl<'a> ChatContext<'a> {
         }
     }
 
+    /// Re-reads the chat settings from the server.
+    pub async fn refresh(&mut self) -> anyhow::Result<ChatSettings> {
+        let settings = self.api.get_chat_settings(&self.inner.chat_code).await?;
+        self.inner.title = settings.title.clone();
+        self.inner.bot = settings.default_bot.clone();
+        self.inner.creation_time = settings.creation_time;
+        Ok(settings)
+    }
+
+    /// Cached chat settings, fetched on first use. Updates received over the
+    /// websocket while a message is streamed are applied to the cache.
+    pub async fn settings(&mut self) -> anyhow::Result<ChatSettings> {
+        match self.api.chat_settings.get(&self.inner.chat_id) {
+            Some(settings) => Ok(settings.clone()),
+            None => self.refresh().await,
+        }
+    }
+
+    pub async fn set_point_threshold(&mut self, points: usize) -> anyhow::Result<bool> {
+        self.api
+            .set_chat_point_threshold(self.inner.chat_id, points)
+            .await
+    }
+
+    pub async fn point_threshold(&mut self) -> anyhow::Result<Option<i64>> {
+        self.api
+            .get_chat_point_threshold(&self.inner.chat_code)
+            .await
+    }
+
     pub async fn set_context_optimization(&mut self, value: bool) -> anyhow::Result<bool> {
         self.api
             .set_chat_context_optimization(self.inner.chat_id, value)
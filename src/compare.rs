@@ -0,0 +1,405 @@
+use std::time::{Duration, Instant};
+
+use futures_util::{future::join_all, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::time;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+use crate::{
+    api::{execute_request, parse_message_response, parse_messages, PoeApi},
+    chat::Chat,
+    constants::MESSAGE_POLL_INTERVAL,
+    message::{FinalState, ReplyState, Text},
+    models::{
+        message::Message,
+        on_message::{MessageQueue, MessageType, OnMessage},
+        FileInput,
+    },
+    utils::{generate_file, get_json_value},
+};
+
+/// A piece of one bot's reply, as yielded by [`Comparison::next_chunk`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompareChunk {
+    pub bot: String,
+    pub text: Text,
+}
+
+/// Final reply of one bot in a [`Comparison`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BotReply {
+    pub bot: String,
+    pub text: String,
+    pub state: FinalState,
+    /// Why the message could not be sent or completed.
+    pub error: Option<String>,
+    pub chat: Option<Chat>,
+    pub cost_points: Option<i64>,
+    pub time_to_first_token: Option<Duration>,
+    pub latency: Duration,
+}
+
+#[derive(Debug)]
+struct Entry {
+    bot: String,
+    chat: Option<Chat>,
+    /// `None` when the message could not be sent.
+    reply: Option<ReplyState>,
+    state: Option<FinalState>,
+    error: Option<String>,
+    finished_at: Option<Instant>,
+}
+
+impl Entry {
+    fn failed(bot: String, error: String) -> Self {
+        Self {
+            bot,
+            chat: None,
+            reply: None,
+            state: Some(FinalState::Failed),
+            error: Some(error),
+            finished_at: Some(Instant::now()),
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.state.is_some()
+    }
+
+    fn finish(&mut self, state: FinalState) {
+        if self.state.is_none() {
+            self.state = Some(state);
+            self.finished_at = Some(Instant::now());
+        }
+    }
+
+    fn chat_id(&self) -> Option<i64> {
+        self.chat.as_ref().map(|chat| chat.chat_id)
+    }
+
+    fn apply(&mut self, message: MessageQueue, is_polled: bool) -> Option<Text> {
+        let reply = self.reply.as_mut()?;
+        let text = match message.payload {
+            MessageType::MessageCancelled => {
+                reply.is_cancelled = true;
+                None
+            }
+            MessageType::JobUpdated(job) if job.state.starts_with("complete") => {
+                reply.is_completed = true;
+                None
+            }
+            MessageType::MessageAdded(m) => reply.apply(m, is_polled),
+            _ => None,
+        };
+        if let Some(Text::Error(error)) = &text {
+            self.error = Some(error.trim().to_string());
+        }
+        let state = reply.is_finished().then(|| reply.final_state());
+        if let Some(state) = state {
+            self.finish(state);
+        }
+        text
+    }
+}
+
+/// Replies of several bots to the same prompt, streamed side by side over the
+/// shared websocket.
+#[derive(Debug)]
+pub struct Comparison<'a> {
+    api: &'a mut PoeApi,
+    entries: Vec<Entry>,
+    started_at: Instant,
+    is_polling: bool,
+    polled_at: Option<Instant>,
+}
+
+impl<'a> Comparison<'a> {
+    pub(crate) async fn start(
+        api: &'a mut PoeApi,
+        prompt: &str,
+        bots: &[&str],
+        files: Vec<FileInput<'_>>,
+    ) -> anyhow::Result<Self> {
+        let files = generate_file(&files).await?;
+        api.connect_websocket().await?;
+
+        let mut requests = vec![];
+        for bot in bots {
+            let request = match api.message_request(bot, prompt, None, files.clone()).await {
+                Ok((bot, request_data)) => api
+                    .build_request(request_data)
+                    .await
+                    .map(|request| (bot, request)),
+                Err(err) => Err(err),
+            };
+            requests.push(request);
+        }
+
+        let started_at = Instant::now();
+        let responses = join_all(requests.into_iter().map(|request| async move {
+            let (bot, request) = request?;
+            let response = execute_request(request).await?;
+            let (chat, _, bot_message) = parse_message_response(&response, &bot)?;
+            anyhow::Ok((chat, bot_message))
+        }))
+        .await;
+
+        let entries = bots
+            .iter()
+            .zip(responses)
+            .map(|(bot, response)| match response {
+                Ok((chat, bot_message)) => {
+                    let mut reply = ReplyState::new(bot_message, api.message_timeouts);
+                    reply.started_at = started_at;
+                    Entry {
+                        bot: bot.to_string(),
+                        chat: Some(chat),
+                        reply: Some(reply),
+                        state: None,
+                        error: None,
+                        finished_at: None,
+                    }
+                }
+                Err(err) => Entry::failed(bot.to_string(), err.to_string()),
+            })
+            .collect();
+
+        Ok(Self {
+            api,
+            entries,
+            started_at,
+            is_polling: false,
+            polled_at: None,
+        })
+    }
+
+    /// Next piece of any bot's reply, in arrival order. `None` once every bot
+    /// is done.
+    pub async fn next_chunk(&mut self) -> Option<CompareChunk> {
+        loop {
+            if let Some(chunk) = self.drain_queues() {
+                return Some(chunk);
+            }
+            if self.entries.iter().all(|entry| entry.is_done()) {
+                return None;
+            }
+            if let Some(chunk) = self.check_deadlines().await {
+                return Some(chunk);
+            }
+            if let Err(err) = self.receive().await {
+                if !self.is_polling {
+                    log::warn!("Lost live updates ({}), polling replies instead", err);
+                    self.is_polling = true;
+                }
+            }
+        }
+    }
+
+    /// Waits for every reply and fetches its point cost.
+    pub async fn results(mut self) -> anyhow::Result<Vec<BotReply>> {
+        while self.next_chunk().await.is_some() {}
+
+        let mut replies = vec![];
+        for entry in self.entries {
+            let cost_points = match (&entry.chat, &entry.reply) {
+                (Some(chat), Some(reply)) => {
+                    cost_points(self.api, chat, &reply.bot_message).await.ok()
+                }
+                _ => None,
+            };
+            let reply = entry.reply.as_ref();
+            replies.push(BotReply {
+                bot: entry.bot,
+                text: reply.map(|reply| reply.text.clone()).unwrap_or_default(),
+                state: entry.state.unwrap_or(FinalState::Failed),
+                error: entry.error,
+                chat: entry.chat,
+                cost_points,
+                time_to_first_token: reply
+                    .and_then(|reply| reply.first_chunk_at)
+                    .map(|at| at.duration_since(self.started_at)),
+                latency: entry
+                    .finished_at
+                    .unwrap_or_else(Instant::now)
+                    .duration_since(self.started_at),
+            });
+        }
+        Ok(replies)
+    }
+
+    /// Closest deadline of the unfinished replies.
+    fn deadline(&self) -> Option<Instant> {
+        self.entries
+            .iter()
+            .filter(|entry| !entry.is_done())
+            .filter_map(|entry| entry.reply.as_ref()?.deadline())
+            .map(|(deadline, _)| deadline)
+            .min()
+    }
+
+    /// Times out the first reply whose deadline passed.
+    async fn check_deadlines(&mut self) -> Option<CompareChunk> {
+        let now = Instant::now();
+        for entry in self.entries.iter_mut().filter(|entry| !entry.is_done()) {
+            let (Some(chat_id), Some(reply)) = (entry.chat_id(), entry.reply.as_mut()) else {
+                continue;
+            };
+            let Some((_, kind)) = reply.deadline().filter(|(deadline, _)| *deadline <= now) else {
+                continue;
+            };
+            let timeout = reply.time_out(kind);
+            if reply.timeouts.cancel_on_timeout {
+                match self
+                    .api
+                    .build_request(PoeApi::cancel_message_request(chat_id))
+                    .await
+                {
+                    Ok(request) => {
+                        tokio::spawn(execute_request(request));
+                    }
+                    Err(err) => log::warn!("Failed to cancel timed out message: {}", err),
+                }
+            }
+            entry.error = Some(timeout.to_string());
+            entry.finish(FinalState::TimedOut);
+            return Some(CompareChunk {
+                bot: entry.bot.clone(),
+                text: Text::Error(format!("{}\n", timeout)),
+            });
+        }
+        None
+    }
+
+    /// Queued updates of every chat, finished replies included: the last
+    /// chunks may be queued after the message completed.
+    fn drain_queues(&mut self) -> Option<CompareChunk> {
+        let is_polling = self.is_polling;
+        for entry in self.entries.iter_mut() {
+            let Some(chat_id) = entry.chat_id() else {
+                continue;
+            };
+            if entry.state == Some(FinalState::TimedOut) {
+                continue;
+            }
+            while let Some(message) = self
+                .api
+                .message_queues
+                .get_mut(&chat_id)
+                .and_then(|queue| queue.pop_front())
+            {
+                if let Some(text) = entry.apply(message, is_polling) {
+                    return Some(CompareChunk {
+                        bot: entry.bot.clone(),
+                        text,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// Moves the next websocket events into `message_queues`, or polls the
+    /// unfinished replies once the websocket is gone.
+    async fn receive(&mut self) -> anyhow::Result<()> {
+        if self.is_polling {
+            return self.poll().await;
+        }
+        let deadline = self.deadline();
+        let Some(reader) = &mut self.api.stream_reader else {
+            anyhow::bail!("websocket is not connected");
+        };
+        let message = match deadline {
+            Some(deadline) => match time::timeout_at(deadline.into(), reader.next()).await {
+                Ok(message) => message,
+                Err(_) => return Ok(()),
+            },
+            None => reader.next().await,
+        };
+
+        match message {
+            Some(Ok(WsMessage::Text(message))) => {
+                if let Ok(on_message) = serde_json::from_str::<OnMessage>(&message) {
+                    for message in on_message.messages {
+                        if message.payload == MessageType::RefetchChannel {
+                            anyhow::bail!("channel must be refetched");
+                        }
+                        self.api
+                            .message_queues
+                            .entry(message.chat_id)
+                            .or_default()
+                            .push_back(message);
+                    }
+                }
+                Ok(())
+            }
+            Some(Ok(WsMessage::Close(_))) => anyhow::bail!("websocket closed"),
+            Some(Ok(_)) => Ok(()),
+            Some(Err(err)) => Err(err.into()),
+            None => anyhow::bail!("websocket closed"),
+        }
+    }
+
+    async fn poll(&mut self) -> anyhow::Result<()> {
+        if let Some(polled_at) = self.polled_at {
+            let next_poll = polled_at + Duration::from_secs(MESSAGE_POLL_INTERVAL);
+            let wake_at = self
+                .deadline()
+                .map_or(next_poll, |deadline| deadline.min(next_poll));
+            time::sleep_until(wake_at.into()).await;
+            if wake_at < next_poll {
+                return Ok(());
+            }
+        }
+        self.polled_at = Some(Instant::now());
+
+        for entry in self.entries.iter().filter(|entry| !entry.is_done()) {
+            let (Some(chat), Some(reply)) = (&entry.chat, &entry.reply) else {
+                continue;
+            };
+            let bot_message = &reply.bot_message;
+            let response = self
+                .api
+                .send_request(PoeApi::chat_page_request(&chat.chat_code))
+                .await?;
+            let message = get_json_value(&response, "data.chatOfCode")
+                .map(parse_messages)
+                .unwrap_or_default()
+                .into_iter()
+                .rev()
+                .find(|m| m.message_id >= bot_message.message_id && !m.is_human());
+            if let Some(message) = message {
+                self.api
+                    .message_queues
+                    .entry(chat.chat_id)
+                    .or_default()
+                    .push_back(MessageQueue {
+                        subscription_name: "messageAdded".into(),
+                        chat_id: chat.chat_id,
+                        message_id: Some(message.message_id),
+                        payload: MessageType::MessageAdded(message.into()),
+                        ..Default::default()
+                    });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Cost of a reply. A reply replaced by another message lost its code on the
+/// way, so it is read again from the chat.
+async fn cost_points(api: &mut PoeApi, chat: &Chat, bot_message: &Message) -> anyhow::Result<i64> {
+    let message_code = if bot_message.message_code.is_empty() {
+        api.get_chat(&chat.chat_code)
+            .await?
+            .messages
+            .into_iter()
+            .find(|m| m.message_id == bot_message.message_id)
+            .map(|m| m.message_code)
+            .ok_or_else(|| anyhow::anyhow!("Message {} not found", bot_message.message_id))?
+    } else {
+        bot_message.message_code.clone()
+    };
+    api.get_total_cost_points(&message_code).await
+}
@@ -50,6 +50,23 @@ lazy_static! {
     pub static ref BOT_NICKNAME: Regex = Regex::new(r#"nickname":"([^"]+)"#).unwrap();
 }
 
+/*
+* +-----------+
+* | REDACTION |
+* +-----------+
+*/
+
+lazy_static! {
+    pub static ref EMAIL_PATTERN: Regex =
+        Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").unwrap();
+    /// Grouped numbers like `(555) 123-4567` or `+62 812-3456-7890`, or `+`
+    /// and 8 to 15 digits. Dates, decimals and plain digit runs don't match.
+    pub static ref PHONE_PATTERN: Regex = Regex::new(
+        r"(?:\+\d{1,3}[ -]?)?(?:\(\d{2,4}\) ?|\b\d{2,4}[ -])\d{3,4}[ -]\d{3,4}\b|\+\d{8,15}\b"
+    )
+    .unwrap();
+}
+
 /*
 * +------+
 * | BASE |
@@ -58,6 +75,10 @@ lazy_static! {
 
 pub const BASE_URL: &str = "https://poe.com";
 pub const DEFAULT_CATEGORY_NAME: &str = "defaultCategory";
+/// Seconds to wait for `chatTitleUpdated` once a reply is done.
+pub const CHAT_TITLE_TIMEOUT: u64 = 10;
+/// Seconds between `ChatPageQuery` polls once the websocket is lost.
+pub const MESSAGE_POLL_INTERVAL: u64 = 2;
 
 pub fn default_headers() -> HeaderMap<HeaderValue> {
     let mut map = HeaderMap::new();
@@ -0,0 +1,262 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{
+    api::PoeApi,
+    chat::Chat,
+    models::{message::Message, reply::Reply, SendMessageData},
+};
+
+pub const DEFAULT_SUMMARY_PROMPT: &str = "Summarize the following conversation so it can be continued without it. Keep every fact, decision and open question, and reply with the summary only.\n\n{transcript}";
+pub const DEFAULT_SEED_PROMPT: &str =
+    "Summary of our conversation so far:\n\n{summary}\n\nContinue from here.";
+
+/// What happens when a context segment grows past the [`ContextPolicy`] limits.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ContextAction {
+    /// Insert a chat break: the bot forgets the segment.
+    Break,
+    /// Summarize the segment with `bot` and start the next segment with the
+    /// summary. `prompt` contains `{transcript}`, `seed` contains `{summary}`.
+    #[serde(rename_all = "camelCase")]
+    Summarize {
+        bot: String,
+        prompt: String,
+        seed: String,
+    },
+}
+
+impl ContextAction {
+    pub fn summarize(bot: impl Into<String>) -> Self {
+        Self::Summarize {
+            bot: bot.into(),
+            prompt: DEFAULT_SUMMARY_PROMPT.to_string(),
+            seed: DEFAULT_SEED_PROMPT.to_string(),
+        }
+    }
+}
+
+/// Limits of a context segment, the messages since the last chat break.
+/// Checked before each message is sent.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextPolicy {
+    /// Maximum user/bot exchanges per segment.
+    pub max_turns: Option<usize>,
+    /// Maximum characters of user and bot text per segment, including the
+    /// message about to be sent.
+    pub max_chars: Option<usize>,
+    pub action: ContextAction,
+}
+
+impl ContextPolicy {
+    pub fn new(action: ContextAction) -> Self {
+        Self {
+            max_turns: None,
+            max_chars: None,
+            action,
+        }
+    }
+
+    pub fn max_turns(mut self, turns: usize) -> Self {
+        self.max_turns = Some(turns);
+        self
+    }
+
+    pub fn max_chars(mut self, chars: usize) -> Self {
+        self.max_chars = Some(chars);
+        self
+    }
+
+    pub fn is_exceeded(&self, turns: usize, chars: usize) -> bool {
+        self.max_turns.is_some_and(|max| turns >= max)
+            || self.max_chars.is_some_and(|max| chars > max)
+    }
+}
+
+/// A multi-turn conversation with one bot. The chat is created on the first
+/// [`Conversation::ask`], and every turn is kept in a local transcript.
+///
+/// A conversation holds no connection: pass the [`PoeApi`] to each call, and
+/// serialize it (or use [`Conversation::persist_to`]) to resume it elsewhere.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Conversation {
+    bot: String,
+    chat: Option<Chat>,
+    messages: Vec<Message>,
+    #[serde(default)]
+    policy: Option<ContextPolicy>,
+    /// Index in `messages` where the current context segment starts.
+    #[serde(default)]
+    segment_start: usize,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Conversation {
+    pub fn new(bot: impl Into<String>) -> Self {
+        Self {
+            bot: bot.into(),
+            chat: None,
+            messages: vec![],
+            policy: None,
+            segment_start: 0,
+            path: None,
+        }
+    }
+
+    /// Continues an existing chat. Earlier messages are not part of the transcript.
+    pub fn resume(bot: impl Into<String>, chat: Chat) -> Self {
+        Self {
+            chat: Some(chat),
+            ..Self::new(bot)
+        }
+    }
+
+    /// Loads a conversation saved by [`Conversation::save`] and keeps saving it
+    /// to the same file.
+    pub async fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let data = fs::read(path.as_ref()).await?;
+        let mut conversation = serde_json::from_slice::<Self>(&data)?;
+        conversation.path = Some(path.as_ref().to_path_buf());
+        Ok(conversation)
+    }
+
+    /// Saves the transcript to `path` after every turn.
+    pub fn persist_to(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub async fn save(&self) -> anyhow::Result<()> {
+        if let Some(path) = &self.path {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                fs::create_dir_all(parent).await?;
+            }
+            fs::write(path, serde_json::to_vec_pretty(self)?).await?;
+        }
+        Ok(())
+    }
+
+    /// Breaks or summarizes the context automatically, see [`ContextPolicy`].
+    pub fn with_policy(mut self, policy: ContextPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    pub fn bot(&self) -> &str {
+        &self.bot
+    }
+
+    /// The chat backing the conversation, `None` until the first message.
+    pub fn chat(&self) -> Option<&Chat> {
+        self.chat.as_ref()
+    }
+
+    /// User and bot messages since the conversation started or was reset,
+    /// oldest first.
+    pub fn history(&self) -> &[Message] {
+        &self.messages
+    }
+
+    /// Messages of the current context segment, what the bot still remembers.
+    pub fn segment(&self) -> &[Message] {
+        &self.messages[self.segment_start.min(self.messages.len())..]
+    }
+
+    pub async fn ask(&mut self, api: &mut PoeApi, text: &str) -> anyhow::Result<Reply> {
+        self.apply_policy(api, text).await?;
+        self.send(api, text).await
+    }
+
+    async fn send(&mut self, api: &mut PoeApi, text: &str) -> anyhow::Result<Reply> {
+        let reply = api
+            .send_and_wait(SendMessageData {
+                bot_handle: &self.bot,
+                message: text,
+                chat_id: self.chat.as_ref().map(|chat| chat.chat_id),
+                files: vec![],
+            })
+            .await?;
+        self.record(&reply).await?;
+        Ok(reply)
+    }
+
+    async fn record(&mut self, reply: &Reply) -> anyhow::Result<()> {
+        self.chat = Some(reply.chat.clone());
+        self.messages.push(reply.user_message.clone());
+        self.messages.push(reply.bot_message.clone());
+        self.save().await
+    }
+
+    async fn apply_policy(&mut self, api: &mut PoeApi, text: &str) -> anyhow::Result<()> {
+        let (Some(policy), Some(chat)) = (self.policy.clone(), self.chat.clone()) else {
+            return Ok(());
+        };
+        let segment = self.segment();
+        let turns = segment.iter().filter(|m| !m.is_human()).count();
+        let chars = segment.iter().map(|m| m.text.len()).sum::<usize>() + text.len();
+        if segment.is_empty() || !policy.is_exceeded(turns, chars) {
+            return Ok(());
+        }
+
+        let summary = match &policy.action {
+            ContextAction::Break => None,
+            ContextAction::Summarize { bot, prompt, .. } => {
+                let transcript = segment
+                    .iter()
+                    .map(|m| {
+                        let author = if m.is_human() { "User" } else { "Assistant" };
+                        format!("{}: {}", author, m.text.trim())
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                let reply = api
+                    .send_and_wait(SendMessageData {
+                        bot_handle: bot,
+                        message: &prompt.replace("{transcript}", &transcript),
+                        ..Default::default()
+                    })
+                    .await?;
+                // the summary chat is only a scratchpad
+                if let Err(err) = api.delete_chat(reply.chat.chat_id).await {
+                    log::warn!("Failed to delete summary chat: {}", err);
+                }
+                Some(reply.text)
+            }
+        };
+
+        log::info!(
+            "Context segment reached {} turns / {} chars, starting a new one",
+            turns,
+            chars
+        );
+        if !api.clear_chat_context(chat.chat_id).await? {
+            anyhow::bail!("Failed to clear the context of chat {}", chat.chat_code);
+        }
+        self.segment_start = self.messages.len();
+
+        if let (Some(summary), ContextAction::Summarize { seed, .. }) = (summary, &policy.action) {
+            self.send(api, &seed.replace("{summary}", &summary)).await?;
+        }
+        Ok(())
+    }
+
+    /// Clears the bot's context on Poe and starts a fresh transcript.
+    pub async fn reset(&mut self, api: &mut PoeApi) -> anyhow::Result<bool> {
+        let is_success = match &self.chat {
+            Some(chat) => api.clear_chat_context(chat.chat_id).await?,
+            None => true,
+        };
+        if is_success {
+            self.messages.clear();
+            self.segment_start = 0;
+            self.save().await?;
+        }
+        Ok(is_success)
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+
+use futures_util::{FutureExt, Stream, StreamExt};
+use serde::Serialize;
+use serde_json::Value;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+use crate::{
+    api::PoeApi,
+    models::on_message::{MessageQueue, MessageType, OnMessage},
+    utils::find_json_key,
+};
+
+const ACCOUNT_SUBSCRIPTIONS: [&str; 3] = [
+    "unreadChatsUpdated",
+    "messagePointLimitUpdated",
+    "viewerStateUpdated",
+];
+
+/// An account-level update, as yielded by [`PoeApi::events`]. The payloads
+/// are not documented by Poe: known fields are read when present, and `raw`
+/// always holds the whole payload.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+pub enum AccountEvent {
+    /// `unreadChatsUpdated`
+    UnreadChats { count: Option<i64>, raw: Value },
+    /// `messagePointLimitUpdated`
+    PointBalance { balance: Option<i64>, raw: Value },
+    /// `viewerStateUpdated`
+    ViewerState { raw: Value },
+}
+
+impl AccountEvent {
+    fn from_queue(message: &MessageQueue) -> Option<Self> {
+        let MessageType::Raw(raw) = &message.payload else {
+            return None;
+        };
+        let raw = raw.clone();
+        match message.subscription_name.as_str() {
+            "unreadChatsUpdated" => Some(Self::UnreadChats {
+                count: find_json_key(&raw, "unreadChatCount").and_then(|v| v.as_i64()),
+                raw,
+            }),
+            "messagePointLimitUpdated" => Some(Self::PointBalance {
+                balance: find_json_key(&raw, "messagePointBalance").and_then(|v| v.as_i64()),
+                raw,
+            }),
+            "viewerStateUpdated" => Some(Self::ViewerState { raw }),
+            _ => None,
+        }
+    }
+}
+
+/// Stream of [`AccountEvent`]s over the websocket. Chat events read on the way
+/// are dropped: no message stream can run while this one borrows the api. The
+/// stream ends when the websocket is lost: call [`PoeApi::events`] again to
+/// reconnect.
+pub struct AccountEvents<'a> {
+    api: &'a mut PoeApi,
+    events: VecDeque<AccountEvent>,
+}
+
+impl<'a> AccountEvents<'a> {
+    pub(crate) fn new(api: &'a mut PoeApi) -> Self {
+        Self {
+            api,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Account events queued by message streams before this one started.
+    fn pop_queued(&mut self) -> Option<AccountEvent> {
+        for queue in self.api.message_queues.values_mut() {
+            let Some(index) = queue
+                .iter()
+                .position(|m| ACCOUNT_SUBSCRIPTIONS.contains(&m.subscription_name.as_str()))
+            else {
+                continue;
+            };
+            if let Some(message) = queue.remove(index) {
+                if let Some(event) = AccountEvent::from_queue(&message) {
+                    return Some(event);
+                }
+            }
+        }
+        None
+    }
+
+    async fn next_event(&mut self) -> Option<AccountEvent> {
+        loop {
+            if let Some(event) = self.events.pop_front().or_else(|| self.pop_queued()) {
+                return Some(event);
+            }
+            let reader = self.api.stream_reader.as_mut()?;
+            let message = match reader.next().await {
+                Some(Ok(WsMessage::Text(message))) => message,
+                Some(Ok(WsMessage::Close(_))) | None => {
+                    log::warn!("Websocket closed");
+                    self.api.stream_reader = None;
+                    continue;
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(err)) => {
+                    log::warn!("Websocket error: {}", err);
+                    self.api.stream_reader = None;
+                    continue;
+                }
+            };
+            let Ok(on_message) = serde_json::from_str::<OnMessage>(&message) else {
+                continue;
+            };
+            for message in on_message.messages {
+                if message.payload == MessageType::RefetchChannel {
+                    log::warn!("Channel must be refetched");
+                    self.api.stream_reader = None;
+                    break;
+                }
+                self.events.extend(AccountEvent::from_queue(&message));
+            }
+        }
+    }
+}
+
+impl Stream for AccountEvents<'_> {
+    type Item = AccountEvent;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        Box::pin(self.next_event()).poll_unpin(cx)
+    }
+}
@@ -0,0 +1,159 @@
+pub mod training;
+pub mod transcript;
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use tokio::fs;
+
+use crate::{api::PoeApi, chat::ChatPage, models::message::Message};
+
+pub use training::{DatasetFormat, DatasetOptions};
+pub use transcript::{ChatTranscript, ExportFormat, TranscriptMessage};
+
+#[derive(Debug, Default, Clone)]
+pub struct ExportFilter {
+    /// Only chats whose default bot has this handle (case-insensitive).
+    pub bot: Option<String>,
+    /// Only messages created at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only messages created before this time.
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl ExportFilter {
+    pub fn matches_bot(&self, bot: Option<&str>) -> bool {
+        match (&self.bot, bot) {
+            (None, _) => true,
+            (Some(expected), Some(bot)) => expected.eq_ignore_ascii_case(bot),
+            (Some(_), None) => false,
+        }
+    }
+
+    pub fn matches_time(&self, time: DateTime<Utc>) -> bool {
+        self.since.is_none_or(|since| time >= since) && self.until.is_none_or(|until| time < until)
+    }
+}
+
+/// Writes chats to disk as Markdown, JSON or HTML transcripts.
+pub struct Exporter<'a> {
+    api: &'a mut PoeApi,
+    filter: ExportFilter,
+}
+
+impl<'a> Exporter<'a> {
+    pub fn new(api: &'a mut PoeApi, filter: ExportFilter) -> Self {
+        Self { api, filter }
+    }
+
+    /// Messages of a chat matching the filter, oldest first, or `None` when the
+    /// filter leaves nothing of it.
+    pub async fn messages(
+        &mut self,
+        chat_code: &str,
+    ) -> anyhow::Result<Option<(ChatPage, Vec<Message>)>> {
+        let page = self.api.get_chat(chat_code).await?;
+        if !self.filter.matches_bot(page.chat.bot.as_deref()) {
+            return Ok(None);
+        }
+
+        let mut messages = vec![];
+        for message in page.messages.iter().rev().cloned() {
+            let created_at = message.created_at();
+            // newest first, nothing older can match anymore
+            if self.filter.since.is_some_and(|since| created_at < since) {
+                break;
+            }
+            if self.filter.matches_time(created_at) {
+                messages.push(message);
+            }
+        }
+        if messages.is_empty() {
+            return Ok(None);
+        }
+        messages.reverse();
+        Ok(Some((page, messages)))
+    }
+
+    pub async fn transcript(&mut self, chat_code: &str) -> anyhow::Result<Option<ChatTranscript>> {
+        Ok(self
+            .messages(chat_code)
+            .await?
+            .map(|(page, messages)| ChatTranscript::new(page.chat, &messages)))
+    }
+
+    pub async fn export_chat(
+        &mut self,
+        chat_code: &str,
+        format: ExportFormat,
+        output_dir: &Path,
+    ) -> anyhow::Result<Option<PathBuf>> {
+        let Some(transcript) = self.transcript(chat_code).await? else {
+            return Ok(None);
+        };
+
+        fs::create_dir_all(output_dir).await?;
+        let path = output_dir.join(format!("{}.{}", chat_code, format.extension()));
+        fs::write(&path, transcript.render(format)?).await?;
+        log::info!("Exported chat {} to {}", chat_code, path.display());
+        Ok(Some(path))
+    }
+
+    pub async fn export_all(
+        &mut self,
+        format: ExportFormat,
+        output_dir: &Path,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        let mut paths = vec![];
+        for chat_code in self.chat_codes().await {
+            if let Some(path) = self.export_chat(&chat_code, format, output_dir).await? {
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Writes a fine-tuning dataset (JSONL) built from the given chat, or from
+    /// every chat. Returns the number of conversations written.
+    pub async fn export_dataset(
+        &mut self,
+        chat_code: Option<&str>,
+        format: DatasetFormat,
+        options: &DatasetOptions,
+        path: &Path,
+    ) -> anyhow::Result<usize> {
+        let chat_codes = match chat_code {
+            Some(chat_code) => vec![chat_code.to_string()],
+            None => self.chat_codes().await,
+        };
+
+        let mut output = String::new();
+        for chat_code in chat_codes {
+            if let Some((_, messages)) = self.messages(&chat_code).await? {
+                output.push_str(&training::to_jsonl(&messages, format, options)?);
+            }
+        }
+
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(path, &output).await?;
+        Ok(output.lines().count())
+    }
+
+    async fn chat_codes(&mut self) -> Vec<String> {
+        let mut chat_codes = vec![];
+        let mut history = self.api.chat_history();
+        while let Some(chat) = history.next().await {
+            chat_codes.push(chat.chat_code);
+        }
+        if !history.is_exhausted() {
+            log::warn!(
+                "Chat history ended early, exporting the first {} chat(s) only",
+                chat_codes.len()
+            );
+        }
+        chat_codes
+    }
+}
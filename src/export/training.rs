@@ -0,0 +1,152 @@
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+
+use crate::{
+    constants::{EMAIL_PATTERN, PHONE_PATTERN},
+    models::message::Message,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatasetFormat {
+    /// `{"messages": [{"role": "user", "content": ...}, ...]}`
+    ChatCompletions,
+    /// `{"conversations": [{"from": "human", "value": ...}, ...]}`
+    ShareGpt,
+}
+
+impl FromStr for DatasetFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "openai" | "chat-completions" => Ok(Self::ChatCompletions),
+            "sharegpt" => Ok(Self::ShareGpt),
+            _ => Err(format!("unknown dataset format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DatasetOptions {
+    /// Conversations with fewer user/assistant exchanges are dropped.
+    pub min_turns: usize,
+    /// Replace email addresses and phone numbers with placeholders.
+    pub redact: bool,
+}
+
+impl Default for DatasetOptions {
+    fn default() -> Self {
+        Self {
+            min_turns: 1,
+            redact: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Turn {
+    pub role: Role,
+    pub content: String,
+}
+
+pub fn redact(text: &str) -> String {
+    let text = EMAIL_PATTERN.replace_all(text, "[EMAIL]");
+    PHONE_PATTERN.replace_all(&text, "[PHONE]").into_owned()
+}
+
+/// Splits a chat (oldest message first) into conversations at chat breaks.
+/// Consecutive messages of the same role are merged, conversations start with
+/// the user and end with the assistant.
+pub fn conversations(messages: &[Message], options: &DatasetOptions) -> Vec<Vec<Turn>> {
+    let mut conversations = vec![];
+    for segment in messages.split(|message| message.is_chat_break()) {
+        let mut turns: Vec<Turn> = vec![];
+        for message in segment {
+            let content = if options.redact {
+                redact(message.text.trim())
+            } else {
+                message.text.trim().to_string()
+            };
+            if content.is_empty() {
+                continue;
+            }
+            let role = if message.is_human() {
+                Role::User
+            } else {
+                Role::Assistant
+            };
+            match turns.last_mut() {
+                Some(last) if last.role == role => {
+                    last.content.push_str("\n\n");
+                    last.content.push_str(&content);
+                }
+                None if role == Role::Assistant => {}
+                _ => turns.push(Turn { role, content }),
+            }
+        }
+        if turns.last().is_some_and(|turn| turn.role == Role::User) {
+            turns.pop();
+        }
+
+        let exchanges = turns
+            .iter()
+            .filter(|turn| turn.role == Role::Assistant)
+            .count();
+        if exchanges > 0 && exchanges >= options.min_turns {
+            conversations.push(turns);
+        }
+    }
+    conversations
+}
+
+pub fn to_record(turns: &[Turn], format: DatasetFormat) -> Value {
+    match format {
+        DatasetFormat::ChatCompletions => {
+            let messages = turns
+                .iter()
+                .map(|turn| {
+                    let role = match turn.role {
+                        Role::User => "user",
+                        Role::Assistant => "assistant",
+                    };
+                    json!({"role": role, "content": turn.content})
+                })
+                .collect::<Vec<_>>();
+            json!({ "messages": messages })
+        }
+        DatasetFormat::ShareGpt => {
+            let conversations = turns
+                .iter()
+                .map(|turn| {
+                    let from = match turn.role {
+                        Role::User => "human",
+                        Role::Assistant => "gpt",
+                    };
+                    json!({"from": from, "value": turn.content})
+                })
+                .collect::<Vec<_>>();
+            json!({ "conversations": conversations })
+        }
+    }
+}
+
+/// JSONL lines for a chat, one per conversation.
+pub fn to_jsonl(
+    messages: &[Message],
+    format: DatasetFormat,
+    options: &DatasetOptions,
+) -> anyhow::Result<String> {
+    let mut output = String::new();
+    for turns in conversations(messages, options) {
+        output.push_str(&serde_json::to_string(&to_record(&turns, format))?);
+        output.push('\n');
+    }
+    Ok(output)
+}
@@ -0,0 +1,195 @@
+use std::{fmt::Write, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{
+    chat::Chat,
+    models::message::{Attachment, Message},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+    Html,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Json => "json",
+            Self::Html => "html",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "markdown" | "md" => Ok(Self::Markdown),
+            "json" => Ok(Self::Json),
+            "html" => Ok(Self::Html),
+            _ => Err(format!("unknown export format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscriptMessage {
+    pub message_id: i64,
+    pub message_code: String,
+    pub author: String,
+    pub is_human: bool,
+    /// Marker left by clearing the context, rendered as a separator.
+    pub is_chat_break: bool,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+    pub attachments: Vec<Attachment>,
+}
+
+/// A chat and its messages, oldest first, ready to be rendered.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatTranscript {
+    pub chat: Chat,
+    pub bot: Option<String>,
+    pub messages: Vec<TranscriptMessage>,
+}
+
+impl ChatTranscript {
+    pub fn new(chat: Chat, messages: &[Message]) -> Self {
+        let bot = chat.bot.clone();
+        let messages = messages
+            .iter()
+            .map(|message| TranscriptMessage {
+                message_id: message.message_id,
+                message_code: message.message_code.clone(),
+                author: if message.is_human() {
+                    "You".to_string()
+                } else {
+                    message.author.clone()
+                },
+                is_human: message.is_human(),
+                is_chat_break: message.is_chat_break(),
+                text: message.text.clone(),
+                created_at: message.created_at(),
+                attachments: message.attachments.clone(),
+            })
+            .collect();
+        Self {
+            chat,
+            bot,
+            messages,
+        }
+    }
+
+    pub fn title(&self) -> String {
+        self.chat
+            .title
+            .clone()
+            .filter(|title| !title.is_empty())
+            .unwrap_or(self.chat.chat_code.clone())
+    }
+
+    pub fn render(&self, format: ExportFormat) -> anyhow::Result<String> {
+        match format {
+            ExportFormat::Markdown => Ok(self.to_markdown()),
+            ExportFormat::Json => self.to_json(),
+            ExportFormat::Html => Ok(self.to_html()),
+        }
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut output = format!("# {}\n\n", self.title());
+        if let Some(bot) = &self.bot {
+            let _ = writeln!(output, "- Bot: {}", bot);
+        }
+        let _ = writeln!(output, "- Chat code: {}\n", self.chat.chat_code);
+
+        for message in &self.messages {
+            if message.is_chat_break {
+                output.push_str("---\n\n_Context cleared_\n\n");
+                continue;
+            }
+            let _ = write!(
+                output,
+                "---\n\n**{}** _{}_\n\n{}\n\n",
+                message.author,
+                message.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                message.text.trim()
+            );
+            for attachment in &message.attachments {
+                let _ = writeln!(output, "- [{}]({})", attachment.name, attachment.url);
+            }
+        }
+        output
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut body = String::new();
+        for message in &self.messages {
+            if message.is_chat_break {
+                body.push_str("<hr class=\"chat-break\" title=\"Context cleared\">\n");
+                continue;
+            }
+            let class = if message.is_human { "human" } else { "bot" };
+            let _ = write!(
+                body,
+                "<div class=\"message {}\"><div class=\"meta\"><b>{}</b> <time>{}</time></div><div class=\"text\">{}</div>",
+                class,
+                escape_html(&message.author),
+                message.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                escape_html(message.text.trim())
+            );
+            for attachment in &message.attachments {
+                let _ = write!(
+                    body,
+                    "<div class=\"attachment\"><a href=\"{}\">{}</a></div>",
+                    escape_html(&attachment.url),
+                    escape_html(&attachment.name)
+                );
+            }
+            body.push_str("</div>\n");
+        }
+
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; max-width: 800px; margin: 2em auto; color: #222; }}
+.message {{ padding: 0.75em 1em; margin: 0.75em 0; border-radius: 8px; }}
+.human {{ background: #eef2ff; }}
+.bot {{ background: #f4f4f5; }}
+.meta {{ font-size: 0.85em; color: #555; margin-bottom: 0.4em; }}
+.text {{ white-space: pre-wrap; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+{body}</body>
+</html>
+"#,
+            title = escape_html(&self.title()),
+            body = body
+        )
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+use crate::chat::Chat;
+
+pub const FORK_REPLAY_PROMPT: &str = "Here is a conversation so far. Continue it from where it stops, answering as the assistant from now on.\n\n{transcript}";
+
+/// How a fork carries the parent transcript into the new chat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ForkMode {
+    /// Send the transcript as one context prompt, see [`FORK_REPLAY_PROMPT`].
+    #[default]
+    Replay,
+    /// Share the messages and continue the shared chat, keeping them as real
+    /// chat messages.
+    ShareImport,
+}
+
+/// A parent/child link between two chats.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatFork {
+    pub parent: Chat,
+    /// Last parent message carried into the child.
+    pub message_id: i64,
+    pub child: Chat,
+    pub bot: String,
+    pub mode: ForkMode,
+}
+
+/// Forks made through [`crate::chat::ChatContext::fork`], kept on
+/// `PoeApi::forks`. Serializable, so the tree can be saved and shown later.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ForkTree {
+    pub forks: Vec<ChatFork>,
+}
+
+impl ForkTree {
+    pub fn record(&mut self, fork: ChatFork) {
+        self.forks.push(fork);
+    }
+
+    pub fn parent_of(&self, chat_id: i64) -> Option<&ChatFork> {
+        self.forks.iter().find(|fork| fork.child.chat_id == chat_id)
+    }
+
+    pub fn children_of(&self, chat_id: i64) -> Vec<&ChatFork> {
+        self.forks
+            .iter()
+            .filter(|fork| fork.parent.chat_id == chat_id)
+            .collect()
+    }
+
+    /// Parents of a chat, closest first.
+    pub fn ancestors_of(&self, chat_id: i64) -> Vec<&ChatFork> {
+        let mut ancestors = vec![];
+        let mut current = chat_id;
+        while let Some(fork) = self.parent_of(current) {
+            // a malformed tree must not loop forever
+            if ancestors.len() > self.forks.len() {
+                break;
+            }
+            current = fork.parent.chat_id;
+            ancestors.push(fork);
+        }
+        ancestors
+    }
+
+    /// Indented outline of the forks below `chat_id`.
+    pub fn render(&self, chat_id: i64) -> String {
+        let mut output = String::new();
+        self.render_children(chat_id, 0, &mut output);
+        output
+    }
+
+    fn render_children(&self, chat_id: i64, depth: usize, output: &mut String) {
+        if depth > self.forks.len() {
+            return;
+        }
+        for fork in self.children_of(chat_id) {
+            output.push_str(&format!(
+                "{}- {} ({}) from message {} with {}\n",
+                "  ".repeat(depth),
+                fork.child.title.as_deref().unwrap_or(&fork.child.chat_code),
+                fork.child.chat_code,
+                fork.message_id,
+                fork.bot
+            ));
+            self.render_children(fork.child.chat_id, depth + 1, output);
+        }
+    }
+}
@@ -1,85 +1,85 @@
-use std::{collections::VecDeque, task::Poll};
+use chrono::{DateTime, Utc};
+use regex::Regex;
 
-use futures_util::{FutureExt, Stream};
-use serde_json::{json, Value};
+use crate::{api::PoeApi, chat::Chat, models::query::QueryHash, paginator::Paginator};
 
-use crate::{api::PoeApi, chat::Chat, models::query::QueryHash, queries::RequestData, utils::get_json_value};
+pub type ChatHistory<'a> = Paginator<'a, Chat>;
 
-#[derive(Debug)]
-pub struct ChatHistory<'a> {
-    api: &'a mut PoeApi,
-    cursor: Option<String>,
-    results: VecDeque<Chat>,
-    is_completed: bool,
+/// Criteria for [`ChatHistory::filter`]. Unset fields match every chat.
+#[derive(Debug, Default, Clone)]
+pub struct ChatFilter {
+    /// Handle of the chat's default bot (case-insensitive).
+    pub bot: Option<String>,
+    pub title: Option<Regex>,
+    /// Last activity at or after this time.
+    pub active_after: Option<DateTime<Utc>>,
+    /// Last activity before this time.
+    pub active_before: Option<DateTime<Utc>>,
 }
 
-impl Stream for ChatHistory<'_> {
-    type Item = Chat;
+impl ChatFilter {
+    pub fn bot(mut self, handle: impl Into<String>) -> Self {
+        self.bot = Some(handle.into());
+        self
+    }
 
-    fn poll_next(
-        mut self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<Self::Item>> {
-        let mut fut = Box::pin(self.next_item());
+    pub fn title(mut self, pattern: &str) -> anyhow::Result<Self> {
+        self.title = Some(Regex::new(pattern)?);
+        Ok(self)
+    }
 
-        loop {
-            match fut.poll_unpin(cx) {
-                Poll::Ready(Ok(item)) => return Poll::Ready(item),
-                Poll::Ready(Err(_)) => return Poll::Ready(None),
-                _ => {}
-            }
-        }
+    pub fn active_after(mut self, time: DateTime<Utc>) -> Self {
+        self.active_after = Some(time);
+        self
     }
-}
 
-impl<'a> ChatHistory<'a> {
-    pub fn new(api: &'a mut PoeApi) -> Self {
-        Self {
-            api,
-            cursor: None,
-            results: VecDeque::new(),
-            is_completed: false,
-        }
+    pub fn active_before(mut self, time: DateTime<Utc>) -> Self {
+        self.active_before = Some(time);
+        self
     }
 
-    async fn next_item(&mut self) -> anyhow::Result<Option<Chat>> {
-        while !self.is_completed || !self.results.is_empty() {
-            if let Some(bot_info) = self.results.pop_front() {
-                return Ok(Some(bot_info));
+    pub fn matches(&self, chat: &Chat) -> bool {
+        let bot = match (&self.bot, &chat.bot) {
+            (None, _) => true,
+            (Some(expected), Some(bot)) => expected.eq_ignore_ascii_case(bot),
+            (Some(_), None) => false,
+        };
+        let title = self.title.as_ref().is_none_or(|pattern| {
+            chat.title
+                .as_deref()
+                .is_some_and(|title| pattern.is_match(title))
+        });
+        let active = match chat.last_active_at() {
+            Some(time) => {
+                self.active_after.is_none_or(|after| time >= after)
+                    && self.active_before.is_none_or(|before| time < before)
             }
+            None => self.active_after.is_none() && self.active_before.is_none(),
+        };
+        bot && title && active
+    }
 
-            let mut data = json!({
-                "count": 10,
-            });
-            if let (Some(cursor), Some(object)) = (self.cursor.clone(), data.as_object_mut()) {
-                object.insert("cursor".into(), Value::String(cursor));
-            }
+    /// The history is sorted by last activity, newest first: past a chat older
+    /// than `active_after` nothing can match anymore.
+    fn is_past_range(&self, chat: &Chat) -> bool {
+        matches!(
+            (self.active_after, chat.last_active_at()),
+            (Some(after), Some(time)) if time < after
+        )
+    }
+}
 
-            let response = self
-                .api
-                .send_request(RequestData {
-                    query_name: QueryHash::ChatHistoryListPaginationQuery,
-                    data,
-                    ..Default::default()
-                })
-                .await?;
+impl<'a> ChatHistory<'a> {
+    pub fn new(api: &'a mut PoeApi) -> Self {
+        Paginator::from_query(api, QueryHash::ChatHistoryListPaginationQuery, "data.chats")
+            .page_size(10)
+    }
 
-            self.is_completed = true;
-            if let Some(data) = get_json_value(&response, "data.chats") {
-                self.cursor = get_json_value(data, "pageInfo.endCursor")
-                    .and_then(|v| v.as_str())
-                    .map(|v| v.to_string());
-                if let Some(items) = get_json_value(data, "edges").and_then(|v| v.as_array()) {
-                    for item in items {
-                        if let Some(node) = get_json_value(item, "node") {
-                            let chat = serde_json::from_value::<Chat>(node.clone())?;
-                            self.results.push_back(chat);
-                            self.is_completed = false;
-                        }
-                    }
-                }
-            }
-        }
-        Ok(None)
+    /// Only yields chats matching `filter`, and stops paginating once the
+    /// remaining chats are older than `filter.active_after`.
+    pub fn filter(self, filter: ChatFilter) -> Self {
+        let stop = filter.clone();
+        self.stop_when(move |chat| stop.is_past_range(chat))
+            .retain(move |chat| filter.matches(chat))
     }
 }
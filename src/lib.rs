@@ -1,9 +1,18 @@
 pub mod api;
+#[cfg(feature = "archive")]
+pub mod archive;
 pub mod bot;
 pub mod chat;
+pub mod compare;
+pub mod conversation;
+pub mod events;
+pub mod export;
+pub mod fork;
 pub mod history;
 pub mod message;
 pub mod models;
+pub mod orchestrator;
+pub mod paginator;
 pub mod search;
 
 mod bundles;
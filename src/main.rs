@@ -1,26 +1,43 @@
 #[cfg(feature = "cli")]
+use std::path::PathBuf;
+
+#[cfg(feature = "cli")]
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+#[cfg(feature = "cli")]
 use futures_util::StreamExt;
 use poe_api::models::FileInput;
 #[cfg(feature = "cli")]
 use poe_api::{
     api::PoeApi,
+    chat::DeletionStatus,
+    compare::BotReply,
+    export::{DatasetFormat, DatasetOptions, ExportFilter, ExportFormat, Exporter},
+    history::ChatFilter,
+    message::Text,
     models::{SendMessageData, Token},
 };
 
+#[cfg(all(feature = "cli", feature = "archive"))]
+use poe_api::archive::Archive;
+
 #[cfg(feature = "cli")]
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 #[cfg(feature = "cli")]
 #[derive(Parser, Debug)]
 #[clap(
     name = "poe-cli",
     about = "A CLI for interacting with Poe",
-    long_about = "This command-line interface allows you to send queries to the Poe.com."
+    long_about = "This command-line interface allows you to send queries to the Poe.com.",
+    subcommand_negates_reqs = true
 )]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// The query to send to the bot.
-    #[clap(value_name = "QUERY")]
-    query: String,
+    #[clap(value_name = "QUERY", required = true)]
+    query: Option<String>,
 
     /// Content value of cookie p-b.
     #[clap(long, value_name = "P_B", env = "POE_P_B", hide_env_values = true)]
@@ -31,7 +48,12 @@ struct Args {
     p_lat: String,
 
     /// Unique code for each poe.com account.
-    #[clap(long, value_name = "FROMKEY", env = "POE_FROMKEY", hide_env_values = true)]
+    #[clap(
+        long,
+        value_name = "FROMKEY",
+        env = "POE_FROMKEY",
+        hide_env_values = true
+    )]
     fromkey: Option<String>,
 
     /// Specify one of the bot names on poe.com.
@@ -47,6 +69,286 @@ struct Args {
     chat_id: Option<i64>,
 }
 
+#[cfg(feature = "cli")]
+impl Args {
+    fn file_inputs(&self) -> Vec<FileInput<'_>> {
+        self.files
+            .iter()
+            .map(|v| {
+                if v.starts_with("http") {
+                    FileInput::Url(v)
+                } else {
+                    FileInput::Local(v.into())
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "cli")]
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Export chats as Markdown, JSON or HTML files.
+    Export {
+        /// Chat code to export. Every chat is exported when omitted.
+        #[clap(short, long, value_name = "CHAT_CODE")]
+        chat: Option<String>,
+
+        /// Output format: markdown, json or html.
+        #[clap(short = 'F', long, value_name = "FORMAT", default_value = "markdown")]
+        format: ExportFormat,
+
+        /// Directory the files are written to.
+        #[clap(short, long, value_name = "DIR", default_value = ".")]
+        output: PathBuf,
+
+        /// Only chats with this bot.
+        #[clap(short = 'b', long, value_name = "BOT_NAME")]
+        bot: Option<String>,
+
+        /// Only messages from this date on (YYYY-MM-DD).
+        #[clap(long, value_name = "DATE")]
+        since: Option<NaiveDate>,
+
+        /// Only messages before this date (YYYY-MM-DD).
+        #[clap(long, value_name = "DATE")]
+        until: Option<NaiveDate>,
+    },
+
+    /// Export chats as a fine-tuning dataset (JSONL).
+    Dataset {
+        /// Chat code to export. Every chat is exported when omitted.
+        #[clap(short, long, value_name = "CHAT_CODE")]
+        chat: Option<String>,
+
+        /// Output format: openai or sharegpt.
+        #[clap(short = 'F', long, value_name = "FORMAT", default_value = "openai")]
+        format: DatasetFormat,
+
+        /// File the dataset is written to.
+        #[clap(short, long, value_name = "FILE", default_value = "dataset.jsonl")]
+        output: PathBuf,
+
+        /// Only chats with this bot.
+        #[clap(short = 'b', long, value_name = "BOT_NAME")]
+        bot: Option<String>,
+
+        /// Drop conversations with fewer user/assistant exchanges.
+        #[clap(long, value_name = "N", default_value_t = 1)]
+        min_turns: usize,
+
+        /// Replace email addresses and phone numbers with placeholders.
+        #[clap(long)]
+        redact: bool,
+    },
+
+    /// Send one query to several bots at once and show the replies side by side.
+    Compare {
+        /// Bots to compare, repeat for each one.
+        #[clap(short = 'b', long = "bot", value_name = "BOT_NAME", required = true)]
+        bots: Vec<String>,
+
+        /// The query to send to every bot.
+        #[clap(value_name = "QUERY")]
+        query: String,
+
+        /// Print the replies as JSON instead of columns.
+        #[clap(long)]
+        json: bool,
+
+        /// Total width of the columns.
+        #[clap(short, long, value_name = "N", default_value_t = 120)]
+        width: usize,
+    },
+
+    /// Manage chats in bulk.
+    Chats {
+        #[clap(subcommand)]
+        command: ChatsCommand,
+    },
+
+    /// Local SQLite mirror of chats and messages.
+    #[cfg(feature = "archive")]
+    Archive {
+        /// Path of the database file.
+        #[clap(long, value_name = "FILE", default_value = "poe-archive.db")]
+        db: PathBuf,
+
+        #[clap(subcommand)]
+        command: ArchiveCommand,
+    },
+}
+
+#[cfg(feature = "cli")]
+#[derive(Subcommand, Debug)]
+enum ChatsCommand {
+    /// Delete chats matching every given criterion.
+    Prune {
+        /// Only chats inactive for this long, e.g. 30d, 12h or 2w.
+        #[clap(long, value_name = "AGE", value_parser = parse_age, required_unless_present = "bot")]
+        older_than: Option<Duration>,
+
+        /// Only chats with this bot.
+        #[clap(short = 'b', long, value_name = "BOT_NAME")]
+        bot: Option<String>,
+
+        /// List the chats that would be deleted without deleting them.
+        #[clap(long)]
+        dry_run: bool,
+    },
+}
+
+#[cfg(all(feature = "cli", feature = "archive"))]
+#[derive(Subcommand, Debug)]
+enum ArchiveCommand {
+    /// Fetch new chats and messages into the archive.
+    Sync,
+
+    /// Full-text search over archived messages.
+    Search {
+        /// FTS5 query, e.g. `rust AND async` or `"exact phrase"`.
+        #[clap(value_name = "QUERY")]
+        query: String,
+
+        /// Maximum number of results.
+        #[clap(short, long, value_name = "N", default_value_t = 20)]
+        limit: usize,
+    },
+}
+
+#[cfg(feature = "cli")]
+fn parse_age(value: &str) -> Result<Duration, String> {
+    let unit_start = value.char_indices().last().map_or(0, |(index, _)| index);
+    let (amount, unit) = value.split_at(unit_start);
+    let amount = amount
+        .parse::<i64>()
+        .map_err(|_| format!("invalid age: {}", value))?;
+    if amount <= 0 {
+        return Err(format!("age must be greater than zero: {}", value));
+    }
+    let age = match unit {
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        "w" => Duration::try_weeks(amount),
+        _ => return Err(format!("invalid age unit in {}, expected h, d or w", value)),
+    };
+    age.ok_or_else(|| format!("age is too large: {}", value))
+}
+
+#[cfg(feature = "cli")]
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let mut word = word.to_string();
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            // words longer than a column are hard-wrapped
+            while word.chars().count() > width {
+                let rest = word.split_off(word.char_indices().nth(width).map_or(0, |(i, _)| i));
+                lines.push(std::mem::replace(&mut word, rest));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(feature = "cli")]
+fn print_columns(replies: &[BotReply], width: usize) {
+    let separator = " | ";
+    let column =
+        (width.saturating_sub(separator.len() * (replies.len() - 1)) / replies.len()).max(10);
+    let columns = replies
+        .iter()
+        .map(|reply| {
+            let mut lines = vec![reply.bot.clone(), "-".repeat(column)];
+            match &reply.error {
+                Some(error) => {
+                    lines.extend(wrap_text(&format!("[{:?}] {}", reply.state, error), column))
+                }
+                None => lines.extend(wrap_text(&reply.text, column)),
+            }
+            lines.push("-".repeat(column));
+            lines.push(format!(
+                "{:.1}s, {} point(s)",
+                reply.latency.as_secs_f64(),
+                reply.cost_points.map_or("?".to_string(), |p| p.to_string())
+            ));
+            lines
+        })
+        .collect::<Vec<_>>();
+
+    let rows = columns.iter().map(Vec::len).max().unwrap_or_default();
+    for row in 0..rows {
+        let line = columns
+            .iter()
+            .map(|lines| {
+                let cell = lines.get(row).map(String::as_str).unwrap_or_default();
+                format!(
+                    "{}{}",
+                    cell,
+                    " ".repeat(column.saturating_sub(cell.chars().count()))
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(separator);
+        println!("{}", line.trim_end());
+    }
+}
+
+#[cfg(feature = "cli")]
+fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()
+}
+
+#[cfg(feature = "cli")]
+async fn send_query(api: &mut PoeApi, opt: &Args, query: &str) -> anyhow::Result<()> {
+    let mut message = api
+        .send_message(SendMessageData {
+            bot_handle: &opt.bot_handle,
+            message: query,
+            chat_id: opt.chat_id,
+            files: opt.file_inputs(),
+        })
+        .await?;
+
+    while let Some(chunk) = message.next().await {
+        chunk.print()?;
+    }
+
+    if !message.text().await.is_empty() {
+        eprintln!("{}", "-".repeat(25));
+        if let Some(title) = message.title_updates().await? {
+            eprintln!("- Chat title: {}", title);
+        }
+        eprintln!("- Chat id: {}", message.chat().inner.chat_id);
+        eprintln!("- Shareable  url: {}", message.share().await?);
+        let preview_apps = message.get_list_preview_app().await?;
+        if !preview_apps.is_empty() {
+            eprintln!("- Preview app list:");
+            for (index, url) in preview_apps.iter().enumerate() {
+                eprintln!("  - App-{}: {}", index, url);
+            }
+        }
+        eprintln!(
+            "- Total message cost point: {}",
+            message.total_cost_points().await?
+        );
+        eprintln!(
+            "- Remaining point: {}",
+            api.get_settings().await?.message_point_balance()
+        );
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     #[cfg(feature = "cli")]
@@ -59,6 +361,27 @@ async fn main() -> anyhow::Result<()> {
 
         let opt = Args::parse();
 
+        // searching the archive needs no connection
+        #[cfg(feature = "archive")]
+        if let Some(Command::Archive {
+            db,
+            command: ArchiveCommand::Search { query, limit },
+        }) = &opt.command
+        {
+            for hit in Archive::open(db)?.search(query, *limit)? {
+                println!(
+                    "{} [{}] {}{}\n  {}: {}\n",
+                    hit.created_at.format("%Y-%m-%d %H:%M"),
+                    hit.chat_code,
+                    hit.title.as_deref().unwrap_or_default(),
+                    if hit.is_deleted { " (deleted)" } else { "" },
+                    hit.author,
+                    hit.snippet
+                );
+            }
+            return Ok(());
+        }
+
         let mut api = PoeApi::new(Token {
             p_b: &opt.p_b,
             p_lat: &opt.p_lat,
@@ -66,50 +389,170 @@ async fn main() -> anyhow::Result<()> {
         })
         .await?;
 
-        let mut message = api
-            .send_message(SendMessageData {
-                bot_handle: &opt.bot_handle,
-                message: &opt.query,
-                chat_id: opt.chat_id,
-                files: opt
-                    .files
-                    .iter()
-                    .map(|v| {
-                        if v.starts_with("http") {
-                            FileInput::Url(v)
-                        } else {
-                            FileInput::Local(v.into())
-                        }
-                    })
-                    .collect(),
-            })
-            .await?;
-
-        while let Some(chunk) = message.next().await {
-            chunk.print()?;
-        }
+        match &opt.command {
+            Some(Command::Export {
+                chat,
+                format,
+                output,
+                bot,
+                since,
+                until,
+            }) => {
+                let filter = ExportFilter {
+                    bot: bot.clone(),
+                    since: since.map(start_of_day),
+                    until: until.map(start_of_day),
+                };
+                let mut exporter = Exporter::new(&mut api, filter);
+                let paths = match chat {
+                    Some(chat_code) => exporter
+                        .export_chat(chat_code, *format, output)
+                        .await?
+                        .into_iter()
+                        .collect(),
+                    None => exporter.export_all(*format, output).await?,
+                };
+                eprintln!("- Exported {} chat(s) to {}", paths.len(), output.display());
+            }
+            Some(Command::Dataset {
+                chat,
+                format,
+                output,
+                bot,
+                min_turns,
+                redact,
+            }) => {
+                let filter = ExportFilter {
+                    bot: bot.clone(),
+                    ..Default::default()
+                };
+                let options = DatasetOptions {
+                    min_turns: *min_turns,
+                    redact: *redact,
+                };
+                let count = Exporter::new(&mut api, filter)
+                    .export_dataset(chat.as_deref(), *format, &options, output)
+                    .await?;
+                eprintln!("- Wrote {} conversation(s) to {}", count, output.display());
+            }
+            Some(Command::Compare {
+                bots,
+                query,
+                json,
+                width,
+            }) => {
+                let bots = bots.iter().map(String::as_str).collect::<Vec<_>>();
+                let mut comparison = api.compare(query, &bots, opt.file_inputs()).await?;
+                let mut progress = bots.iter().map(|bot| (*bot, 0)).collect::<Vec<_>>();
+                while let Some(chunk) = comparison.next_chunk().await {
+                    if let Some((_, chars)) = progress.iter_mut().find(|(bot, _)| *bot == chunk.bot)
+                    {
+                        *chars = match &chunk.text {
+                            Text::Chunk(text) => *chars + text.chars().count(),
+                            Text::Full(text) => text.chars().count(),
+                            _ => *chars,
+                        };
+                    }
+                    let status = progress
+                        .iter()
+                        .map(|(bot, chars)| format!("{}: {} chars", bot, chars))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    eprint!("\r- {}", status);
+                }
+                eprintln!();
 
-        if !message.text().await.is_empty() {
-            eprintln!("{}", "-".repeat(25));
-            eprintln!("- Chat title: {}", message.title());
-            eprintln!("- Chat id: {}", message.chat().inner.chat_id);
-            eprintln!("- Shareable  url: {}", message.share().await?);
-            let preview_apps = message.get_list_preview_app().await?;
-            if !preview_apps.is_empty() {
-                eprintln!("- Preview app list:");
-                for (index, url) in preview_apps.iter().enumerate() {
-                    eprintln!("  - App-{}: {}", index, url);
+                let replies = comparison.results().await?;
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&replies)?);
+                } else {
+                    print_columns(&replies, *width);
+                }
+            }
+            Some(Command::Chats {
+                command:
+                    ChatsCommand::Prune {
+                        older_than,
+                        bot,
+                        dry_run,
+                    },
+            }) => {
+                let filter = ChatFilter {
+                    bot: bot.clone(),
+                    active_before: older_than.map(|age| Utc::now() - age),
+                    ..Default::default()
+                };
+                let deletions = api
+                    .delete_chats_where(|chat| filter.matches(chat), *dry_run)
+                    .await?;
+                for deletion in &deletions {
+                    let status = match &deletion.status {
+                        DeletionStatus::DryRun => "would delete".to_string(),
+                        DeletionStatus::Deleted => "deleted".to_string(),
+                        DeletionStatus::Failed(reason) => format!("failed: {}", reason),
+                    };
+                    eprintln!(
+                        "- [{}] {} {}",
+                        deletion.chat.chat_code,
+                        deletion.chat.title.as_deref().unwrap_or_default(),
+                        status
+                    );
+                }
+                eprintln!("- {} chat(s) matched", deletions.len());
+            }
+            #[cfg(feature = "archive")]
+            Some(Command::Archive { db, command }) => {
+                if let ArchiveCommand::Sync = command {
+                    let report = Archive::open(db)?.sync(&mut api).await?;
+                    eprintln!(
+                        "- Synced {} chat(s), {} unchanged, {} new message(s), {} deleted chat(s)",
+                        report.chats, report.unchanged, report.messages, report.deleted
+                    );
+                }
+            }
+            None => {
+                if let Some(query) = &opt.query {
+                    send_query(&mut api, &opt, query).await?;
                 }
             }
-            eprintln!(
-                "- Total message cost point: {}",
-                message.total_cost_points().await?
-            );
-            eprintln!(
-                "- Remaining point: {}",
-                api.get_settings().await?.message_point_balance()
-            );
         }
     }
     Ok(())
 }
+
+#[cfg(all(test, feature = "cli"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_age_accepts_units() {
+        assert_eq!(parse_age("12h"), Ok(Duration::hours(12)));
+        assert_eq!(parse_age("30d"), Ok(Duration::days(30)));
+        assert_eq!(parse_age("2w"), Ok(Duration::weeks(2)));
+    }
+
+    #[test]
+    fn parse_age_rejects_non_positive_amounts() {
+        assert!(parse_age("0d").is_err());
+        assert!(parse_age("-1d").is_err());
+    }
+
+    #[test]
+    fn parse_age_rejects_missing_unit() {
+        assert!(parse_age("30").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("").is_err());
+    }
+
+    #[test]
+    fn parse_age_rejects_non_ascii_input() {
+        assert!(parse_age("3ü").is_err());
+        assert!(parse_age("ü").is_err());
+        assert!(parse_age("3日").is_err());
+    }
+
+    #[test]
+    fn parse_age_rejects_overflow() {
+        assert!(parse_age(&format!("{}w", i64::MAX)).is_err());
+    }
+}
@@ -1,16 +1,23 @@
 use std::{
+    fmt::Display,
+    future::Future,
     io::{stderr, Write},
+    pin::Pin,
     thread::sleep,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    api::PoeApi,
+    api::{execute_request, parse_messages, PoeApi},
     chat::{Chat, ChatContext},
+    constants::{CHAT_TITLE_TIMEOUT, MESSAGE_POLL_INTERVAL},
     models::{
         message::Message,
-        on_message::{MessageQueue, MessageType, OnMessage},
+        on_message::{MessageData, MessageQueue, MessageType, OnMessage},
+        reply::Reply,
+        FileInput, SendMessageData,
     },
+    utils::{find_json_key, get_json_value},
 };
 
 #[cfg(feature = "cli")]
@@ -19,17 +26,74 @@ use crossterm::{
     terminal::{self, ClearType},
 };
 use futures_util::{FutureExt, SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    task::JoinHandle,
+    time::{self, Sleep},
+};
 use tokio_tungstenite::tungstenite::Message as WsMessage;
 
+/// Deadlines applied while waiting for a bot reply. `None` disables a deadline.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MessageTimeouts {
+    /// Maximum wait for the first chunk of the reply.
+    pub first_token: Option<Duration>,
+    /// Maximum wait between two chunks.
+    pub idle: Option<Duration>,
+    /// Maximum duration of the whole reply.
+    pub total: Option<Duration>,
+    /// Cancel the generation on Poe when a deadline passes.
+    pub cancel_on_timeout: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutKind {
+    FirstToken,
+    Idle,
+    Total,
+}
+
+#[derive(Debug, Clone)]
+pub struct MessageTimeout {
+    pub kind: TimeoutKind,
+    pub partial_text: String,
+}
+
+impl Display for MessageTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let deadline = match self.kind {
+            TimeoutKind::FirstToken => "first token",
+            TimeoutKind::Idle => "idle",
+            TimeoutKind::Total => "total",
+        };
+        write!(
+            f,
+            "Message timed out ({} deadline) after {} characters",
+            deadline,
+            self.partial_text.chars().count()
+        )
+    }
+}
+
+impl std::error::Error for MessageTimeout {}
+
 pub struct MessageContext<'a> {
     api: &'a mut PoeApi,
     chat: Chat,
     user_message: Message,
-    bot_message: Message,
-    is_completed: bool,
-    is_cancelled: bool,
-    text: String,
+    /// Handle of the replying bot, when known.
+    bot: Option<String>,
+    reply_state: ReplyState,
+    is_done_emitted: bool,
+    is_recovering: bool,
     chat_title: String,
+    timer: Option<Pin<Box<Sleep>>>,
+
+    // polling fallback
+    polled_at: Option<Instant>,
+    pending_poll: Option<JoinHandle<anyhow::Result<Value>>>,
+    pending_refresh: Option<JoinHandle<anyhow::Result<Value>>>,
 }
 
 impl<'a> MessageContext<'a> {
@@ -39,32 +103,181 @@ impl<'a> MessageContext<'a> {
         user_message: Message,
         bot_message: Message,
     ) -> Self {
+        let timeouts = api.message_timeouts;
         Self {
             api,
             chat,
             user_message,
-            bot_message,
-            is_completed: false,
-            is_cancelled: false,
-            text: String::new(),
+            bot: None,
+            reply_state: ReplyState::new(bot_message, timeouts),
+            is_done_emitted: false,
+            is_recovering: false,
             chat_title: String::new(),
+            timer: None,
+
+            polled_at: None,
+            pending_poll: None,
+            pending_refresh: None,
         }
     }
 
+    /// Replays the current state of the bot message as its first update, so the
+    /// stream starts from the text generated so far.
+    pub(crate) fn attached(self) -> Self {
+        let message = MessageQueue {
+            subscription_name: "messageAdded".into(),
+            chat_id: self.chat.chat_id,
+            message_id: Some(self.reply_state.bot_message.message_id),
+            payload: MessageType::MessageAdded(self.reply_state.bot_message.clone().into()),
+            ..Default::default()
+        };
+        self.api
+            .message_queues
+            .entry(self.chat.chat_id)
+            .or_default()
+            .push_front(message);
+        self
+    }
+
+    pub(crate) fn with_bot(mut self, bot: impl Into<String>) -> Self {
+        self.bot = Some(bot.into());
+        self
+    }
+
+    /// Overrides the deadlines inherited from `PoeApi::message_timeouts`.
+    pub fn with_timeouts(mut self, timeouts: MessageTimeouts) -> Self {
+        self.reply_state.timeouts = timeouts;
+        self
+    }
+
+    pub fn timed_out(&self) -> Option<&MessageTimeout> {
+        self.reply_state.timed_out.as_ref()
+    }
+
+    /// Handle of the bot writing this reply, which tells the members of a
+    /// group chat apart. Falls back to the chat's default bot.
+    pub fn bot(&self) -> Option<&str> {
+        self.bot.as_deref().or(self.chat.bot.as_deref())
+    }
+
     pub fn title(&self) -> String {
-        self.chat.title.clone().unwrap_or(self.chat_title.clone())
+        if self.chat_title.is_empty() {
+            self.chat.title.clone().unwrap_or_default()
+        } else {
+            self.chat_title.clone()
+        }
+    }
+
+    /// Waits for the chat title, independently of the reply. Chats that already
+    /// have one return right away; new chats receive theirs through a
+    /// `chatTitleUpdated` event, which may never come for some bots, so the
+    /// wait is capped at `CHAT_TITLE_TIMEOUT`.
+    pub async fn title_updates(&mut self) -> anyhow::Result<Option<String>> {
+        if self.chat_title.is_empty() {
+            if let Some(title) = self.chat.title.clone().filter(|t| !t.is_empty()) {
+                return Ok(Some(title));
+            }
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(CHAT_TITLE_TIMEOUT);
+        while self.chat_title.is_empty() {
+            let Some(reader) = &mut self.api.stream_reader else {
+                break;
+            };
+            match time::timeout_at(deadline.into(), reader.next()).await {
+                Ok(Some(message)) => {
+                    self.handle_websocket_message(message?).await?;
+                }
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        if self.chat_title.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(self.chat_title.clone()))
     }
 
     pub async fn text(&mut self) -> String {
-        if !self.is_completed && !self.is_cancelled {
+        if !self.reply_state.is_finished() {
             while self.next().await.is_some() {
                 // do nothing
             }
         }
-        self.text.clone()
+        self.reply_state.text.clone()
+    }
+
+    /// Like [`MessageContext::text`], but fails with [`MessageTimeout`] (holding the
+    /// partial text) when a deadline passed.
+    pub async fn try_text(&mut self) -> anyhow::Result<String> {
+        let text = self.text().await;
+        if let Some(timeout) = self.reply_state.timed_out.clone() {
+            return Err(timeout.into());
+        }
+        Ok(text)
+    }
+
+    /// Waits for the whole reply and collects it with its cost, when it could be
+    /// read. The chat title is included when already known: new chats may get
+    /// theirs later, see [`MessageContext::title_updates`]. Fails with
+    /// [`MessageTimeout`] like [`MessageContext::try_text`].
+    pub async fn reply(&mut self) -> anyhow::Result<Reply> {
+        self.try_text().await?;
+        Ok(self.partial_reply().await)
     }
 
-    pub fn chat(&mut self) -> ChatContext {
+    /// Like [`MessageContext::reply`], but a timed out reply is returned with
+    /// its partial text and the `TimedOut` state.
+    pub(crate) async fn partial_reply(&mut self) -> Reply {
+        let text = self.text().await;
+        let total_duration = self
+            .reply_state
+            .finished_at
+            .unwrap_or_else(Instant::now)
+            .duration_since(self.reply_state.started_at);
+
+        let mut chat = self.chat.clone();
+        let title = self.title();
+        if !title.is_empty() {
+            chat.title = Some(title);
+        }
+        let cost_points = match self.total_cost_points().await {
+            Ok(cost_points) => Some(cost_points),
+            Err(err) => {
+                log::warn!("Failed to read the cost of the reply: {}", err);
+                None
+            }
+        };
+
+        let mut bot_message = self.reply_state.bot_message.clone();
+        bot_message.text = text.clone();
+
+        Reply {
+            bot: self.bot().map(|bot| bot.to_string()),
+            text,
+            attachments: bot_message.attachments.clone(),
+            chat,
+            user_message: self.user_message.clone(),
+            bot_message,
+            state: self.done().state,
+            cost_points,
+            time_to_first_token: self
+                .reply_state
+                .first_chunk_at
+                .map(|first_chunk_at| first_chunk_at - self.reply_state.started_at),
+            total_duration,
+        }
+    }
+
+    pub fn user_message(&self) -> &Message {
+        &self.user_message
+    }
+
+    pub fn bot_message(&self) -> &Message {
+        &self.reply_state.bot_message
+    }
+
+    pub fn chat(&mut self) -> ChatContext<'_> {
         let chat = self.chat.clone();
         ChatContext::new(self.api, chat)
     }
@@ -73,13 +286,119 @@ impl<'a> MessageContext<'a> {
         self.api.cancel_message(self.chat.chat_id).await
     }
 
-    pub async fn retry(&mut self) -> anyhow::Result<MessageContext> {
-        self.api.retry_message(&self.chat.chat_code).await
+    pub async fn retry(&mut self) -> anyhow::Result<MessageContext<'_>> {
+        let bot = self.bot.clone();
+        let retry = self.api.retry_message(&self.chat.chat_code).await?;
+        Ok(match bot {
+            Some(bot) => retry.with_bot(bot),
+            None => retry,
+        })
+    }
+
+    /// Replaces the question of this exchange: deletes the user/bot pair and
+    /// sends `new_text` to the same chat and bot, with the same attachments.
+    /// Fails unless this exchange is the last one of the chat.
+    pub async fn edit_and_resend(&mut self, new_text: &str) -> anyhow::Result<MessageContext<'_>> {
+        if !self.reply_state.is_finished() {
+            self.cancel().await?;
+        }
+
+        let page = self.api.get_chat(&self.chat.chat_code).await?;
+        if page.messages.last().map(|m| m.message_id)
+            != Some(self.reply_state.bot_message.message_id)
+        {
+            anyhow::bail!(
+                "Message {} is not the last one of chat {}",
+                self.reply_state.bot_message.message_id,
+                self.chat.chat_code
+            );
+        }
+
+        let bot = match self.bot().map(|bot| bot.to_string()) {
+            Some(bot) => bot,
+            None => page
+                .chat
+                .bot
+                .ok_or(anyhow::anyhow!("Chat {} has no bot", self.chat.chat_code))?,
+        };
+
+        if !self
+            .api
+            .delete_messages(
+                &self.chat.chat_code,
+                &[
+                    self.user_message.message_id,
+                    self.reply_state.bot_message.message_id,
+                ],
+            )
+            .await?
+        {
+            anyhow::bail!("Failed to delete message {}", self.user_message.message_id);
+        }
+
+        let urls = self
+            .user_message
+            .attachments
+            .iter()
+            .map(|attachment| attachment.url.clone())
+            .collect::<Vec<_>>();
+        self.api
+            .send_message(SendMessageData {
+                bot_handle: &bot,
+                message: new_text,
+                chat_id: Some(self.chat.chat_id),
+                files: urls.iter().map(|url| FileInput::Url(url)).collect(),
+            })
+            .await
+    }
+
+    /// Collects `n` answers to the same prompt: this reply, then `n - 1`
+    /// regenerations through `retry_message`. Only the last one stays in the chat.
+    pub async fn variants(&mut self, n: usize) -> anyhow::Result<Vec<Variant>> {
+        let mut variants = vec![];
+        if n == 0 {
+            return Ok(variants);
+        }
+        variants.push(self.variant().await?);
+        for _ in 1..n {
+            let mut retry = self.api.retry_message(&self.chat.chat_code).await?;
+            variants.push(retry.variant().await?);
+        }
+        Ok(variants)
+    }
+
+    /// Like [`MessageContext::variants`], keeping the variant with the highest score.
+    pub async fn best_variant<F>(&mut self, n: usize, score: F) -> anyhow::Result<Option<Variant>>
+    where
+        F: Fn(&Variant) -> f64,
+    {
+        let variants = self.variants(n).await?;
+        Ok(variants
+            .into_iter()
+            .map(|variant| (score(&variant), variant))
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, variant)| variant))
+    }
+
+    async fn variant(&mut self) -> anyhow::Result<Variant> {
+        let text = self.text().await;
+        let cost_points = self.total_cost_points().await?;
+        let done = self.done();
+        Ok(Variant {
+            text,
+            message_id: done.message_id,
+            message_code: done.message_code,
+            state: done.state,
+            cost_points,
+        })
     }
 
     pub async fn total_cost_points(&mut self) -> anyhow::Result<i64> {
+        if self.reply_state.bot_message.message_code.is_empty() {
+            self.refresh_bot_message().await?;
+        }
         self.api
-            .get_total_cost_points(&self.bot_message.message_code)
+            .get_total_cost_points(&self.reply_state.bot_message.message_code)
             .await
     }
 
@@ -87,14 +406,17 @@ impl<'a> MessageContext<'a> {
         self.api
             .get_message_share_url(
                 self.chat.chat_id,
-                &[self.user_message.message_id, self.bot_message.message_id],
+                &[
+                    self.user_message.message_id,
+                    self.reply_state.bot_message.message_id,
+                ],
             )
             .await
     }
 
     pub async fn get_list_preview_app(&mut self) -> anyhow::Result<Vec<String>> {
         self.api
-            .get_list_preview_app(self.bot_message.message_id)
+            .get_list_preview_app(self.reply_state.bot_message.message_id)
             .await
     }
 
@@ -102,7 +424,10 @@ impl<'a> MessageContext<'a> {
         self.api
             .delete_messages(
                 &self.chat.chat_code,
-                &[self.bot_message.message_id, self.user_message.message_id],
+                &[
+                    self.reply_state.bot_message.message_id,
+                    self.user_message.message_id,
+                ],
             )
             .await
     }
@@ -115,7 +440,10 @@ impl<'a> MessageContext<'a> {
 
     pub async fn delete_bot_message(&mut self) -> anyhow::Result<bool> {
         self.api
-            .delete_messages(&self.chat.chat_code, &[self.bot_message.message_id])
+            .delete_messages(
+                &self.chat.chat_code,
+                &[self.reply_state.bot_message.message_id],
+            )
             .await
     }
 
@@ -128,7 +456,10 @@ impl<'a> MessageContext<'a> {
         Ok(())
     }
 
-    async fn handle_websocket_message(&mut self, message: WsMessage) -> anyhow::Result<Option<MessageQueue>> {
+    async fn handle_websocket_message(
+        &mut self,
+        message: WsMessage,
+    ) -> anyhow::Result<Option<MessageQueue>> {
         if let WsMessage::Close(_) = message {
             self.reconnect().await?;
             return Ok(None);
@@ -142,24 +473,41 @@ impl<'a> MessageContext<'a> {
         Ok(None)
     }
 
-    async fn process_on_message(&mut self, on_message: OnMessage) -> anyhow::Result<Option<MessageQueue>> {
+    async fn process_on_message(
+        &mut self,
+        on_message: OnMessage,
+    ) -> anyhow::Result<Option<MessageQueue>> {
         for message in on_message.messages {
             if message.payload == MessageType::RefetchChannel {
                 self.reconnect().await?;
                 break;
             } else if message.payload == MessageType::MessageCancelled {
-                self.is_cancelled = true;
+                if self.chat.chat_id == message.chat_id {
+                    self.reply_state.is_cancelled = true;
+                }
             } else if let MessageType::ChatTitleUpdated(ref title) = message.payload {
                 if self.chat.chat_id == message.chat_id {
                     self.chat_title = title.text.clone();
                 }
+                if let Some(settings) = self.api.chat_settings.get_mut(&message.chat_id) {
+                    settings.title = Some(title.text.clone());
+                }
+            } else if let MessageType::ChatSettingsUpdated(ref data) = message.payload {
+                if let Some(settings) = self.api.chat_settings.get_mut(&message.chat_id) {
+                    settings.update(data);
+                }
+            } else if let MessageType::Raw(ref data) = message.payload {
+                if message.subscription_name == "chatMemberAdded" {
+                    let handle = find_json_key(data, "handle").and_then(|v| v.as_str());
+                    if let (Some(settings), Some(handle)) =
+                        (self.api.chat_settings.get_mut(&message.chat_id), handle)
+                    {
+                        settings.add_member(handle);
+                    }
+                }
             } else if let MessageType::JobUpdated(ref job) = message.payload {
-                self.is_completed = job.state.starts_with("complete")
-                    && self.chat.chat_id == message.chat_id;
-            }
-            if self.is_completed && self.chat_title.is_empty() {
-                if let Some(title) = self.chat.title.clone() {
-                    self.chat_title = title;
+                if self.chat.chat_id == message.chat_id && job.state.starts_with("complete") {
+                    self.reply_state.is_completed = true;
                 }
             }
 
@@ -173,7 +521,7 @@ impl<'a> MessageContext<'a> {
     }
 
     async fn read_message(&mut self) -> anyhow::Result<MessageQueue> {
-        while (!self.is_completed && !self.is_cancelled) || self.chat_title.is_empty() {
+        loop {
             // read from cache
             if let Some(message) = self
                 .api
@@ -183,60 +531,435 @@ impl<'a> MessageContext<'a> {
             {
                 return Ok(message);
             }
+            if self.reply_state.is_finished() {
+                break;
+            }
+
+            if let Some(timeout) = &self.reply_state.timed_out {
+                anyhow::bail!(timeout.clone());
+            }
 
-            if let Some(reader) = &mut self.api.stream_reader {
-                if let Some(message) = reader.next().await {
-                    if let Some(processed_message) = self.handle_websocket_message(message?).await? {
-                        return Ok(processed_message);
+            if self.is_recovering {
+                return self.poll_message().await;
+            }
+
+            let deadline = self.deadline();
+            let Some(reader) = &mut self.api.stream_reader else {
+                self.start_recovery("websocket is not connected");
+                continue;
+            };
+            let message = match deadline {
+                Some((deadline, kind)) => {
+                    match time::timeout_at(deadline.into(), reader.next()).await {
+                        Ok(message) => message,
+                        Err(_) => return Err(self.on_timeout(kind).await.into()),
                     }
                 }
+                None => reader.next().await,
+            };
+            let processed_message = match message {
+                Some(Ok(message)) => self.handle_websocket_message(message).await,
+                Some(Err(err)) => Err(err.into()),
+                None => Err(anyhow::anyhow!("websocket closed")),
+            };
+            match processed_message {
+                Ok(Some(processed_message)) => return Ok(processed_message),
+                Ok(None) => {}
+                Err(err) => self.start_recovery(&err.to_string()),
             }
         }
         anyhow::bail!("No more messages or stream completed unexpectedly.")
     }
 
-    async fn next_message(&mut self) -> Option<Text> {
-        if !self.is_cancelled && !self.is_completed {
-            while let Ok(message) = self.read_message().await {
-                if let MessageType::MessageAdded(mut m) = message.payload {
-                    if !matches!(m.state.as_str(), "complete" | "completed" | "incomplete") {
-                        self.is_completed = true;
-                        return Some(Text::Error(format!(
-                            "{}: {}\n",
-                            m.state,
-                            m.message_state_text?.trim()
-                        )));
-                    }
-                    if m.state.starts_with("complete") && !m.text.ends_with("\n") {
-                        m.text = m.text.trim().to_owned();
-                        m.text.push('\n');
-                    }
-                    if m.text.contains("...") && m.text.ends_with("\n") {
-                        m.text = m.text.trim().to_owned();
+    fn start_recovery(&mut self, reason: &str) {
+        log::warn!(
+            "Lost live updates ({}), polling message {} instead",
+            reason,
+            self.reply_state.bot_message.message_id
+        );
+        self.is_recovering = true;
+    }
+
+    /// Fetches the bot message through `ChatPageQuery` and replays it as a
+    /// `messageAdded` update. Used once the websocket is gone.
+    async fn poll_message(&mut self) -> anyhow::Result<MessageQueue> {
+        if self.pending_poll.is_none() {
+            if let Some(next_poll) = self.next_poll() {
+                if let Some((deadline, kind)) = self.deadline() {
+                    if deadline <= next_poll {
+                        time::sleep_until(deadline.into()).await;
+                        return Err(self.on_timeout(kind).await.into());
                     }
+                }
+                time::sleep_until(next_poll.into()).await;
+            }
+            let request = self
+                .api
+                .build_request(PoeApi::chat_page_request(&self.chat.chat_code))
+                .await?;
+            // Runs in its own task, so the request survives this future being dropped.
+            self.pending_poll = Some(tokio::spawn(execute_request(request)));
+            self.polled_at = Some(Instant::now());
+        }
+
+        let Some(pending_poll) = self.pending_poll.as_mut() else {
+            unreachable!("the poll request is spawned above");
+        };
+        let response = pending_poll.await;
+        self.pending_poll = None;
 
-                    if m.text.starts_with(&self.text) {
-                        let (_, chunk) = m.text.split_at(self.text.len());
-                        if !chunk.is_empty() {
-                            self.text = m.text.clone();
-                            return Some(Text::Chunk(chunk.to_string()));
-                        }
-                    } else if m.text.len() > self.text.len() {
-                        self.text = m.text.clone();
-                        return Some(Text::Full(m.text));
+        let response = response??;
+        let message = get_json_value(&response, "data.chatOfCode")
+            .map(parse_messages)
+            .unwrap_or_default()
+            .into_iter()
+            .rev()
+            .find(|m| m.message_id >= self.reply_state.bot_message.message_id && !m.is_human())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Message {} not found",
+                    self.reply_state.bot_message.message_id
+                )
+            })?;
+
+        Ok(MessageQueue {
+            subscription_name: "messageAdded".into(),
+            chat_id: self.chat.chat_id,
+            message_id: Some(message.message_id),
+            payload: MessageType::MessageAdded(message.into()),
+            ..Default::default()
+        })
+    }
+
+    fn next_poll(&self) -> Option<Instant> {
+        self.polled_at
+            .map(|polled_at| polled_at + Duration::from_secs(MESSAGE_POLL_INTERVAL))
+    }
+
+    fn deadline(&self) -> Option<(Instant, TimeoutKind)> {
+        self.reply_state.deadline()
+    }
+
+    async fn on_timeout(&mut self, kind: TimeoutKind) -> MessageTimeout {
+        let timeout = self.reply_state.time_out(kind);
+        if self.reply_state.timeouts.cancel_on_timeout {
+            // Sent from its own task, so the request is not dropped with this future.
+            match self
+                .api
+                .build_request(PoeApi::cancel_message_request(self.chat.chat_id))
+                .await
+            {
+                Ok(request) => {
+                    tokio::spawn(execute_request(request));
+                }
+                Err(err) => log::warn!("Failed to cancel timed out message: {}", err),
+            }
+        }
+        timeout
+    }
+
+    async fn next_message(&mut self) -> Option<Text> {
+        if !self.reply_state.is_finished() {
+            while let Ok(message) = self.read_message().await {
+                if let MessageType::MessageAdded(m) = message.payload {
+                    if let Some(text) = self.reply_state.apply(m, self.is_recovering) {
+                        return Some(text);
                     }
                 }
             }
+            if let Some(timeout) = &self.reply_state.timed_out {
+                self.reply_state.is_completed = true;
+                return Some(Text::Error(format!("{}\n", timeout)));
+            }
+        }
+        if !self.is_done_emitted {
+            self.reply_state
+                .finished_at
+                .get_or_insert_with(Instant::now);
+            // the server tells where the text was cut, and the code of a
+            // message that replaced the first one
+            if self.reply_state.is_cancelled || self.reply_state.bot_message.message_code.is_empty()
+            {
+                if let Err(err) = self.refresh_bot_message().await {
+                    log::warn!("Failed to read the bot message: {}", err);
+                }
+            }
+            self.is_done_emitted = true;
+            return Some(Text::Done(self.done()));
         }
         None
     }
+
+    /// Re-reads the bot message through `ChatPageQuery`, for the fields the
+    /// stream does not carry. Like [`MessageContext::poll_message`], the
+    /// request runs in its own task, so this future can be dropped and retried.
+    async fn refresh_bot_message(&mut self) -> anyhow::Result<()> {
+        if self.pending_refresh.is_none() {
+            let request = self
+                .api
+                .build_request(PoeApi::chat_page_request(&self.chat.chat_code))
+                .await?;
+            self.pending_refresh = Some(tokio::spawn(execute_request(request)));
+        }
+
+        let Some(pending_refresh) = self.pending_refresh.as_mut() else {
+            unreachable!("the refresh request is spawned above");
+        };
+        let response = pending_refresh.await;
+        self.pending_refresh = None;
+
+        let response = response??;
+        let message = get_json_value(&response, "data.chatOfCode")
+            .map(parse_messages)
+            .unwrap_or_default()
+            .into_iter()
+            .find(|m| m.message_id == self.reply_state.bot_message.message_id)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Message {} not found",
+                    self.reply_state.bot_message.message_id
+                )
+            })?;
+        self.reply_state.bot_message.message_code = message.message_code;
+        self.reply_state.bot_message.text_length_on_cancellation =
+            message.text_length_on_cancellation;
+        Ok(())
+    }
+
+    fn done(&self) -> MessageDone {
+        let state = self.reply_state.final_state();
+        let text_length_on_cancellation = (state == FinalState::Cancelled)
+            .then_some(self.reply_state.bot_message.text_length_on_cancellation);
+
+        MessageDone {
+            bot: self.bot().map(|bot| bot.to_string()),
+            message_id: self.reply_state.bot_message.message_id,
+            message_code: self.reply_state.bot_message.message_code.clone(),
+            state,
+            text_length: self.reply_state.text.chars().count(),
+            text_length_on_cancellation,
+            chat: self.chat.clone(),
+        }
+    }
 }
 
+/// Progress of one bot reply: turns its `messageAdded` updates into [`Text`]
+/// and tracks its deadlines. Shared by [`MessageContext`] and
+/// [`crate::compare::Comparison`].
 #[derive(Debug)]
+pub(crate) struct ReplyState {
+    pub(crate) bot_message: Message,
+    pub(crate) text: String,
+    pub(crate) is_completed: bool,
+    pub(crate) is_cancelled: bool,
+    pub(crate) timeouts: MessageTimeouts,
+    pub(crate) started_at: Instant,
+    pub(crate) first_chunk_at: Option<Instant>,
+    pub(crate) last_chunk_at: Option<Instant>,
+    pub(crate) finished_at: Option<Instant>,
+    pub(crate) timed_out: Option<MessageTimeout>,
+}
+
+impl ReplyState {
+    pub(crate) fn new(bot_message: Message, timeouts: MessageTimeouts) -> Self {
+        Self {
+            bot_message,
+            text: String::new(),
+            is_completed: false,
+            is_cancelled: false,
+            timeouts,
+            started_at: Instant::now(),
+            first_chunk_at: None,
+            last_chunk_at: None,
+            finished_at: None,
+            timed_out: None,
+        }
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.is_completed || self.is_cancelled
+    }
+
+    /// Applies a `messageAdded` update of the chat. `is_polled` tells that it
+    /// was read through `ChatPageQuery`, which may differ from the streamed text.
+    pub(crate) fn apply(&mut self, mut m: MessageData, is_polled: bool) -> Option<Text> {
+        // leftovers from earlier replies in the same chat
+        if m.message_id < self.bot_message.message_id {
+            return None;
+        }
+        // replies of other members of a group chat, yielded once complete
+        if m.message_id != self.bot_message.message_id
+            && !self.bot_message.author.is_empty()
+            && m.author != self.bot_message.author
+        {
+            if m.state.starts_with("complete") && m.author != "human" {
+                return Some(Text::Member {
+                    bot: m.author,
+                    text: m.text,
+                });
+            }
+            return None;
+        }
+        if m.state.starts_with("complete") {
+            self.is_completed = true;
+        }
+        if m.message_id != self.bot_message.message_id {
+            // the code of the previous message, read again when needed
+            self.bot_message.message_code.clear();
+        }
+        if let Some(code) = m.message_code.take() {
+            self.bot_message.message_code = code;
+        }
+        self.bot_message.message_id = m.message_id;
+        self.bot_message.state = m.state.clone();
+        if !m.attachments.is_empty() {
+            self.bot_message.attachments = m.attachments.clone();
+        }
+        if let Some(length) = m.text_length_on_cancellation {
+            self.bot_message.text_length_on_cancellation = length;
+        }
+        if !matches!(m.state.as_str(), "complete" | "completed" | "incomplete") {
+            self.is_completed = true;
+            return Some(Text::Error(format!(
+                "{}: {}\n",
+                m.state,
+                m.message_state_text.unwrap_or_default().trim()
+            )));
+        }
+        if m.state.starts_with("complete") && !m.text.ends_with("\n") {
+            m.text = m.text.trim().to_owned();
+            m.text.push('\n');
+        }
+        if m.text.contains("...") && m.text.ends_with("\n") {
+            m.text = m.text.trim().to_owned();
+        }
+
+        if is_polled && self.is_completed && m.text != self.text {
+            self.text = m.text.clone();
+            self.on_chunk();
+            return Some(Text::Full(m.text));
+        }
+        if m.text.starts_with(&self.text) {
+            let (_, chunk) = m.text.split_at(self.text.len());
+            if !chunk.is_empty() {
+                let chunk = chunk.to_string();
+                self.text = m.text;
+                self.on_chunk();
+                return Some(Text::Chunk(chunk));
+            }
+        } else if m.text.len() > self.text.len() {
+            self.text = m.text.clone();
+            self.on_chunk();
+            return Some(Text::Full(m.text));
+        }
+        None
+    }
+
+    fn on_chunk(&mut self) {
+        let now = Instant::now();
+        self.first_chunk_at.get_or_insert(now);
+        self.last_chunk_at = Some(now);
+    }
+
+    /// The closest deadline of [`MessageTimeouts`], with its kind.
+    pub(crate) fn deadline(&self) -> Option<(Instant, TimeoutKind)> {
+        let total = self
+            .timeouts
+            .total
+            .map(|total| (self.started_at + total, TimeoutKind::Total));
+        let chunk = match self.last_chunk_at {
+            Some(last_chunk_at) => self
+                .timeouts
+                .idle
+                .map(|idle| (last_chunk_at + idle, TimeoutKind::Idle)),
+            None => self
+                .timeouts
+                .first_token
+                .map(|first_token| (self.started_at + first_token, TimeoutKind::FirstToken))
+                .or(self
+                    .timeouts
+                    .idle
+                    .map(|idle| (self.started_at + idle, TimeoutKind::Idle))),
+        };
+        total
+            .into_iter()
+            .chain(chunk)
+            .min_by_key(|(deadline, _)| *deadline)
+    }
+
+    pub(crate) fn time_out(&mut self, kind: TimeoutKind) -> MessageTimeout {
+        let timeout = MessageTimeout {
+            kind,
+            partial_text: self.text.clone(),
+        };
+        log::warn!("{}", timeout);
+        self.timed_out = Some(timeout.clone());
+        timeout
+    }
+
+    pub(crate) fn final_state(&self) -> FinalState {
+        if self.timed_out.is_some() {
+            FinalState::TimedOut
+        } else if self.is_cancelled {
+            FinalState::Cancelled
+        } else if matches!(
+            self.bot_message.state.as_str(),
+            "complete" | "completed" | "incomplete"
+        ) {
+            FinalState::Completed
+        } else {
+            FinalState::Failed
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FinalState {
+    Completed,
+    Cancelled,
+    Failed,
+    TimedOut,
+}
+
+/// One of several answers to the same prompt, see [`MessageContext::variants`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Variant {
+    pub text: String,
+    pub message_id: i64,
+    pub message_code: String,
+    pub state: FinalState,
+    pub cost_points: i64,
+}
+
+/// Metadata of a finished bot reply, sent as the last item of the stream.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageDone {
+    pub bot: Option<String>,
+    pub message_id: i64,
+    pub message_code: String,
+    pub state: FinalState,
+    /// Characters of the reply received.
+    pub text_length: usize,
+    /// Where the server cut the reply, as reported in `textLengthOnCancellation`.
+    pub text_length_on_cancellation: Option<i64>,
+    pub chat: Chat,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
 pub enum Text {
     Chunk(String),
     Full(String),
     Error(String),
+    /// Complete reply of another member of a group chat.
+    Member {
+        bot: String,
+        text: String,
+    },
+    Done(MessageDone),
 }
 
 impl Text {
@@ -266,6 +989,11 @@ impl Text {
                 write!(stderr, "{}", s)?;
                 stderr.flush()?;
             }
+            Self::Member { bot, text } => {
+                writeln!(stderr, "\n[{}] {}", bot, text.trim())?;
+                stderr.flush()?;
+            }
+            Self::Done(_) => {}
         }
         Ok(())
     }
@@ -278,7 +1006,26 @@ impl Stream for MessageContext<'_> {
         mut self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        let mut future = Box::pin(self.next_message());
-        future.poll_unpin(cx)
+        let poll = Box::pin(self.next_message()).poll_unpin(cx);
+
+        // The future above is dropped when pending, so keep a timer alive to wake
+        // this stream up when the next deadline or poll is due.
+        if poll.is_pending() {
+            let next_poll = self.next_poll().filter(|_| self.is_recovering);
+            let wake_at = self
+                .deadline()
+                .map(|(deadline, _)| deadline)
+                .into_iter()
+                .chain(next_poll)
+                .min();
+            if let Some(deadline) = wake_at {
+                let timer = self
+                    .timer
+                    .get_or_insert_with(|| Box::pin(time::sleep_until(deadline.into())));
+                timer.as_mut().reset(deadline.into());
+                let _ = timer.as_mut().poll(cx);
+            }
+        }
+        poll
     }
 }
@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{de::Error, Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
@@ -45,6 +46,33 @@ pub struct Message {
     pub message_code: String,
     pub text_length_on_cancellation: i64,
     pub uid: Option<i64>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+}
+
+impl Message {
+    pub fn is_human(&self) -> bool {
+        self.author == "human"
+    }
+
+    /// Marker left by `clear_chat_context`; the bot forgets everything before it.
+    pub fn is_chat_break(&self) -> bool {
+        self.author == "chat_break"
+    }
+
+    /// `creation_time` is in microseconds.
+    pub fn created_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp_micros(self.creation_time).unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Attachment {
+    pub attachment_id: i64,
+    pub name: String,
+    pub url: String,
+    pub is_inline: bool,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -2,6 +2,7 @@ pub mod api_settings;
 pub mod message;
 pub mod on_message;
 pub mod query;
+pub mod reply;
 pub mod user;
 
 use std::path::PathBuf;
@@ -1,6 +1,8 @@
 use serde::{de::Error, Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
+use crate::models::message::{Attachment, Message};
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MessageQueue {
@@ -37,6 +39,29 @@ pub struct MessageData {
     pub message_state_text: Option<String>,
     pub text: String,
     pub author: String,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    #[serde(default)]
+    pub text_length_on_cancellation: Option<i64>,
+    #[serde(default)]
+    pub message_code: Option<String>,
+}
+
+impl From<Message> for MessageData {
+    fn from(message: Message) -> Self {
+        Self {
+            id: message.id,
+            message_id: message.message_id,
+            creation_time: message.creation_time,
+            state: message.state,
+            message_state_text: message.message_state_text,
+            text: message.text,
+            author: message.author,
+            attachments: message.attachments,
+            text_length_on_cancellation: Some(message.text_length_on_cancellation),
+            message_code: Some(message.message_code),
+        }
+    }
 }
 
 #[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
@@ -47,6 +72,7 @@ pub enum MessageType {
     MessageCancelled,
     JobUpdated(JobData),
     ChatTitleUpdated(Title),
+    ChatSettingsUpdated(Value),
     MessageAdded(MessageData),
     Raw(Value),
 }
@@ -105,10 +131,12 @@ where
                     serde_json::to_string(payload_data).map_err(D::Error::custom)?;
                 let hash = format!("{:x}", md5::compute(payload_string));
 
-                let payload = if subscription_name.starts_with("chat") {
+                let payload = if subscription_name == "chatTitleUpdated" {
                     let data = serde_json::from_value::<Title>(payload_data.clone())
                         .map_err(D::Error::custom)?;
                     MessageType::ChatTitleUpdated(data)
+                } else if subscription_name == "chatSettingsUpdated" {
+                    MessageType::ChatSettingsUpdated(payload_data.clone())
                 } else if subscription_name.starts_with("job") {
                     let data = serde_json::from_value::<JobData>(payload_data.clone())
                         .map_err(D::Error::custom)?;
@@ -1,7 +1,7 @@
 use serde::Serialize;
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum QueryHash {
     #[default]
     SubscriptionsMutation,
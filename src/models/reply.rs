@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chat::Chat,
+    message::FinalState,
+    models::message::{Attachment, Message},
+};
+
+/// A finished bot reply, as returned by `PoeApi::send_and_wait`.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Reply {
+    /// Handle of the bot that replied.
+    #[serde(default)]
+    pub bot: Option<String>,
+    pub text: String,
+    pub attachments: Vec<Attachment>,
+    pub chat: Chat,
+    pub user_message: Message,
+    pub bot_message: Message,
+    pub state: FinalState,
+    /// `None` when the cost could not be read.
+    pub cost_points: Option<i64>,
+    pub time_to_first_token: Option<Duration>,
+    pub total_duration: Duration,
+}
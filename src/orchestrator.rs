@@ -0,0 +1,295 @@
+use std::{fmt::Write, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{
+    api::PoeApi,
+    chat::{Chat, ChatContext},
+    message::FinalState,
+    models::{reply::Reply, SendMessageData},
+};
+
+pub const DEFAULT_RELAY_TEMPLATE: &str = "{speaker} said:\n\n{message}";
+
+/// One bot taking part in an [`Orchestrator`] run, with its own chat.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Participant {
+    /// Name used in the transcript and in `{speaker}`.
+    pub name: String,
+    pub bot: String,
+    /// Framing of the previous speaker's output, with `{message}`, `{speaker}`
+    /// and `{round}` placeholders.
+    pub template: String,
+    /// Created on the participant's first turn.
+    pub chat: Option<Chat>,
+}
+
+impl Participant {
+    pub fn new(name: impl Into<String>, bot: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            bot: bot.into(),
+            template: DEFAULT_RELAY_TEMPLATE.to_string(),
+            chat: None,
+        }
+    }
+
+    pub fn template(mut self, template: impl Into<String>) -> Self {
+        self.template = template.into();
+        self
+    }
+
+    /// Continues an existing chat instead of starting a new one.
+    pub fn chat(mut self, chat: Chat) -> Self {
+        self.chat = Some(chat);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Turn {
+    /// Starts at 1. A round ends once every participant has spoken.
+    pub round: usize,
+    pub speaker: String,
+    pub bot: String,
+    pub chat_code: String,
+    /// What was sent to the bot.
+    pub prompt: String,
+    pub text: String,
+    pub state: FinalState,
+    /// 0 when the cost could not be read.
+    pub cost_points: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum StopReason {
+    MaxRounds,
+    StopPhrase {
+        speaker: String,
+    },
+    PointBudget {
+        spent: i64,
+    },
+    /// A reply was cancelled, failed or timed out.
+    Interrupted {
+        speaker: String,
+        state: FinalState,
+    },
+}
+
+/// Relays the output of each bot to the next one, in order, for debates and
+/// critique loops: bot A answers the opening prompt, bot B gets A's answer
+/// framed by its template, A gets B's output, and so on.
+///
+/// Like [`crate::conversation::Conversation`], it holds no connection: pass
+/// the [`PoeApi`] to each call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Orchestrator {
+    opening: String,
+    participants: Vec<Participant>,
+    max_rounds: usize,
+    stop_phrase: Option<String>,
+    point_budget: Option<i64>,
+    turns: Vec<Turn>,
+    stop_reason: Option<StopReason>,
+}
+
+impl Orchestrator {
+    /// `opening` is sent as is to the first participant.
+    pub fn new(opening: impl Into<String>) -> Self {
+        Self {
+            opening: opening.into(),
+            participants: vec![],
+            max_rounds: 3,
+            stop_phrase: None,
+            point_budget: None,
+            turns: vec![],
+            stop_reason: None,
+        }
+    }
+
+    pub fn participant(mut self, participant: Participant) -> Self {
+        self.participants.push(participant);
+        self
+    }
+
+    /// Defaults to 3.
+    pub fn max_rounds(mut self, rounds: usize) -> Self {
+        self.max_rounds = rounds;
+        self
+    }
+
+    /// Stops once a reply contains `phrase` (case-insensitive).
+    pub fn stop_phrase(mut self, phrase: impl Into<String>) -> Self {
+        self.stop_phrase = Some(phrase.into());
+        self
+    }
+
+    /// Stops once the replies cost `points` or more in total.
+    pub fn point_budget(mut self, points: i64) -> Self {
+        self.point_budget = Some(points);
+        self
+    }
+
+    pub fn participants(&self) -> &[Participant] {
+        &self.participants
+    }
+
+    pub fn turns(&self) -> &[Turn] {
+        &self.turns
+    }
+
+    pub fn stop_reason(&self) -> Option<&StopReason> {
+        self.stop_reason.as_ref()
+    }
+
+    pub fn spent_points(&self) -> i64 {
+        self.turns.iter().map(|turn| turn.cost_points).sum()
+    }
+
+    /// Runs turns until a stop condition is met.
+    pub async fn run(&mut self, api: &mut PoeApi) -> anyhow::Result<StopReason> {
+        while self.next_turn(api).await?.is_some() {}
+        Ok(self.stop_reason.clone().unwrap_or(StopReason::MaxRounds))
+    }
+
+    /// Sends one relayed message and returns the turn, or `None` once stopped.
+    pub async fn next_turn(&mut self, api: &mut PoeApi) -> anyhow::Result<Option<&Turn>> {
+        if self.participants.len() < 2 {
+            anyhow::bail!("An orchestrator needs at least two participants");
+        }
+        if self.stop_reason.is_some() {
+            return Ok(None);
+        }
+        let index = self.turns.len() % self.participants.len();
+        let round = self.turns.len() / self.participants.len() + 1;
+        if round > self.max_rounds {
+            self.stop_reason = Some(StopReason::MaxRounds);
+            return Ok(None);
+        }
+
+        let prompt = match self.turns.last() {
+            None => self.opening.clone(),
+            Some(previous) => self.participants[index]
+                .template
+                .replace("{speaker}", &previous.speaker)
+                .replace("{round}", &round.to_string())
+                .replace("{message}", previous.text.trim()),
+        };
+        let participant = &mut self.participants[index];
+        let reply = send(api, participant, &prompt).await?;
+        participant.chat = Some(reply.chat.clone());
+
+        let turn = Turn {
+            round,
+            speaker: participant.name.clone(),
+            bot: participant.bot.clone(),
+            chat_code: reply.chat.chat_code.clone(),
+            prompt,
+            text: reply.text,
+            state: reply.state,
+            cost_points: reply.cost_points.unwrap_or_default(),
+        };
+        log::info!(
+            "Round {}: {} replied with {} chars for {} points",
+            round,
+            turn.speaker,
+            turn.text.len(),
+            turn.cost_points
+        );
+        self.stop_reason = self.check_stop(&turn);
+        self.turns.push(turn);
+        Ok(self.turns.last())
+    }
+
+    fn check_stop(&self, turn: &Turn) -> Option<StopReason> {
+        if turn.state != FinalState::Completed {
+            return Some(StopReason::Interrupted {
+                speaker: turn.speaker.clone(),
+                state: turn.state,
+            });
+        }
+        if let Some(phrase) = &self.stop_phrase {
+            if turn.text.to_lowercase().contains(&phrase.to_lowercase()) {
+                return Some(StopReason::StopPhrase {
+                    speaker: turn.speaker.clone(),
+                });
+            }
+        }
+        let spent = self.spent_points() + turn.cost_points;
+        match self.point_budget {
+            Some(budget) if spent >= budget => Some(StopReason::PointBudget { spent }),
+            _ => None,
+        }
+    }
+
+    /// Every turn in order, as Markdown.
+    pub fn to_markdown(&self) -> String {
+        let mut output = String::from("# Bot conversation\n\n");
+        for participant in &self.participants {
+            let _ = writeln!(output, "- {}: {}", participant.name, participant.bot);
+        }
+        let _ = writeln!(output, "- Points spent: {}", self.spent_points());
+        if let Some(reason) = &self.stop_reason {
+            let _ = writeln!(output, "- Stopped: {:?}", reason);
+        }
+        let _ = write!(
+            output,
+            "\n---\n\n**Opening**\n\n{}\n\n",
+            self.opening.trim()
+        );
+
+        for turn in &self.turns {
+            let _ = write!(
+                output,
+                "---\n\n**{}** _round {}, {} points_\n\n{}\n\n",
+                turn.speaker,
+                turn.round,
+                turn.cost_points,
+                turn.text.trim()
+            );
+        }
+        output
+    }
+
+    /// Writes the combined transcript, as JSON when `path` ends in `.json`
+    /// and as Markdown otherwise.
+    pub async fn write_transcript(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).await?;
+        }
+        let content = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::to_string_pretty(self)?,
+            _ => self.to_markdown(),
+        };
+        fs::write(path, content).await?;
+        Ok(())
+    }
+}
+
+/// A timed out reply is kept with its partial text, and stops the run as
+/// [`StopReason::Interrupted`].
+async fn send(api: &mut PoeApi, participant: &Participant, prompt: &str) -> anyhow::Result<Reply> {
+    let payload = SendMessageData {
+        bot_handle: &participant.bot,
+        message: prompt,
+        ..Default::default()
+    };
+    let reply = match &participant.chat {
+        Some(chat) => {
+            ChatContext::new(api, chat.clone())
+                .send_message(payload)
+                .await?
+                .partial_reply()
+                .await
+        }
+        None => api.send_message(payload).await?.partial_reply().await,
+    };
+    Ok(reply)
+}
@@ -0,0 +1,310 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use futures_util::{FutureExt, Stream};
+use reqwest::RequestBuilder;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+
+use crate::{
+    api::{execute_request, PoeApi},
+    models::query::QueryHash,
+    queries::RequestData,
+    utils::get_json_value,
+};
+
+pub type NodeParser<T> = fn(Value) -> anyhow::Result<T>;
+pub type Predicate<'a, T> = Box<dyn Fn(&T) -> bool + Send + Sync + 'a>;
+
+/// Signs a page request. Owns the api borrow while running, and hands it back.
+type BuildFuture<'a> =
+    Pin<Box<dyn Future<Output = (&'a mut PoeApi, anyhow::Result<RequestBuilder>)> + Send + 'a>>;
+
+/// Walks a GraphQL connection (`pageInfo.endCursor` + `edges[].node`) page by page.
+pub struct Paginator<'a, T> {
+    /// `None` while a request is being built.
+    api: Option<&'a mut PoeApi>,
+    query_name: QueryHash,
+    connection_path: &'static str,
+    variables: Value,
+    parser: NodeParser<T>,
+    page_size: usize,
+    limit: Option<usize>,
+    yielded: usize,
+    cursor: Option<String>,
+    resume_cursor: Option<String>,
+    results: VecDeque<T>,
+    is_completed: bool,
+    prefetch: bool,
+    building: Option<BuildFuture<'a>>,
+    pending: Option<JoinHandle<anyhow::Result<Value>>>,
+    retain: Option<Predicate<'a, T>>,
+    stop_when: Option<Predicate<'a, T>>,
+}
+
+impl<T> fmt::Debug for Paginator<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Paginator")
+            .field("query_name", &self.query_name)
+            .field("connection_path", &self.connection_path)
+            .field("variables", &self.variables)
+            .field("page_size", &self.page_size)
+            .field("limit", &self.limit)
+            .field("yielded", &self.yielded)
+            .field("cursor", &self.cursor)
+            .field("is_completed", &self.is_completed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Unpin> Stream for Paginator<'_, T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(item) = this.pop_result() {
+                return Poll::Ready(Some(item));
+            }
+            if this.is_completed || this.remaining() == Some(0) {
+                return Poll::Ready(None);
+            }
+
+            if let Some(pending) = &mut this.pending {
+                let response = ready!(pending.poll_unpin(cx));
+                this.pending = None;
+                let Ok(Ok(response)) = response else {
+                    return Poll::Ready(None);
+                };
+                if this.push_page(&response).is_err() {
+                    return Poll::Ready(None);
+                }
+                if this.prefetch && !this.is_completed {
+                    this.start_request();
+                    if let Poll::Ready(Err(_)) = this.poll_build(cx) {
+                        return Poll::Ready(None);
+                    }
+                }
+                continue;
+            }
+
+            if this.building.is_none() {
+                this.start_request();
+            }
+            if ready!(this.poll_build(cx)).is_err() {
+                return Poll::Ready(None);
+            }
+        }
+    }
+}
+
+impl<'a, T: DeserializeOwned> Paginator<'a, T> {
+    /// Parses each node with `serde`. See [`Paginator::with_parser`] otherwise.
+    pub fn from_query(
+        api: &'a mut PoeApi,
+        query_name: QueryHash,
+        connection_path: &'static str,
+    ) -> Self {
+        Self::with_parser(api, query_name, connection_path, |node| {
+            Ok(serde_json::from_value(node)?)
+        })
+    }
+}
+
+impl<'a, T> Paginator<'a, T> {
+    pub fn with_parser(
+        api: &'a mut PoeApi,
+        query_name: QueryHash,
+        connection_path: &'static str,
+        parser: NodeParser<T>,
+    ) -> Self {
+        Self {
+            api: Some(api),
+            query_name,
+            connection_path,
+            variables: json!({}),
+            parser,
+            page_size: 10,
+            limit: None,
+            yielded: 0,
+            cursor: None,
+            resume_cursor: None,
+            results: VecDeque::new(),
+            is_completed: false,
+            prefetch: false,
+            building: None,
+            pending: None,
+            retain: None,
+            stop_when: None,
+        }
+    }
+
+    /// Extra query variables sent with every page, besides `count` and `cursor`.
+    pub fn variables(mut self, variables: Value) -> Self {
+        if let (Some(object), Value::Object(variables)) =
+            (self.variables.as_object_mut(), variables)
+        {
+            object.extend(variables);
+        }
+        self
+    }
+
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    /// Stops after `n` items, requesting no more than needed from the server.
+    pub fn take(mut self, n: usize) -> Self {
+        self.limit = Some(n);
+        self
+    }
+
+    /// Starts from a cursor previously returned by [`Paginator::cursor`].
+    pub fn resume_from(mut self, cursor: impl Into<String>) -> Self {
+        self.cursor = Some(cursor.into());
+        self.resume_cursor = self.cursor.clone();
+        self
+    }
+
+    /// Requests the next page in the background while the current one is consumed.
+    pub fn prefetch(mut self, enabled: bool) -> Self {
+        self.prefetch = enabled;
+        self
+    }
+
+    /// Only yields items matching `predicate`. Skipped items do not count
+    /// towards [`Paginator::take`].
+    pub fn retain(mut self, predicate: impl Fn(&T) -> bool + Send + Sync + 'a) -> Self {
+        self.retain = Some(Box::new(predicate));
+        self
+    }
+
+    /// Stops paginating at the first item matching `predicate`, which is not
+    /// yielded. Useful when the connection is ordered and the rest can't match.
+    pub fn stop_when(mut self, predicate: impl Fn(&T) -> bool + Send + Sync + 'a) -> Self {
+        self.stop_when = Some(Box::new(predicate));
+        self
+    }
+
+    /// Cursor of the last fully consumed page. Resuming from it never skips items.
+    pub fn cursor(&self) -> Option<&str> {
+        self.resume_cursor.as_deref()
+    }
+
+    /// Whether the whole connection was walked. A stream that ended on a request
+    /// error is not exhausted.
+    pub fn is_exhausted(&self) -> bool {
+        self.is_completed && self.results.is_empty()
+    }
+
+    fn remaining(&self) -> Option<usize> {
+        self.limit
+            .map(|limit| limit.saturating_sub(self.yielded + self.results.len()))
+    }
+
+    fn request_data(&self) -> RequestData {
+        let count = self
+            .remaining()
+            .map_or(self.page_size, |remaining| remaining.min(self.page_size));
+
+        let mut data = self.variables.clone();
+        if let Some(object) = data.as_object_mut() {
+            object.insert("count".into(), json!(count));
+            if let Some(cursor) = self.cursor.clone() {
+                object.insert("cursor".into(), Value::String(cursor));
+            }
+        }
+
+        RequestData {
+            query_name: self.query_name,
+            data,
+            ..Default::default()
+        }
+    }
+
+    fn push_page(&mut self, response: &Value) -> anyhow::Result<()> {
+        self.is_completed = true;
+        if let Some(data) = get_json_value(response, self.connection_path) {
+            let cursor = get_json_value(data, "pageInfo.endCursor")
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_string());
+            let has_next_page = get_json_value(data, "pageInfo.hasNextPage")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true);
+            // a cursor that does not advance means the same page came back
+            if self.cursor.is_some() && cursor == self.cursor {
+                return Ok(());
+            }
+
+            if let Some(items) = get_json_value(data, "edges").and_then(|v| v.as_array()) {
+                let mut is_stopped = false;
+                for item in items {
+                    if let Some(node) = get_json_value(item, "node") {
+                        let item = (self.parser)(node.clone())?;
+                        self.is_completed = false;
+                        if self.stop_when.as_ref().is_some_and(|stop| stop(&item)) {
+                            is_stopped = true;
+                            break;
+                        }
+                        if self.retain.as_ref().is_none_or(|retain| retain(&item)) {
+                            self.results.push_back(item);
+                        }
+                    }
+                }
+                if is_stopped {
+                    self.is_completed = true;
+                }
+            }
+
+            if !has_next_page || cursor.is_none() || cursor == self.cursor {
+                self.is_completed = true;
+            }
+            self.cursor = cursor;
+        }
+        if self.remaining() == Some(0) {
+            self.is_completed = true;
+        }
+        Ok(())
+    }
+
+    fn pop_result(&mut self) -> Option<T> {
+        let item = self.results.pop_front()?;
+        self.yielded += 1;
+        if self.results.is_empty() {
+            self.resume_cursor = self.cursor.clone();
+        }
+        Some(item)
+    }
+
+    /// Starts signing the request of the next page.
+    fn start_request(&mut self) {
+        let Some(api) = self.api.take() else {
+            return;
+        };
+        let request_data = self.request_data();
+        self.building = Some(Box::pin(async move {
+            let request = api.build_request(request_data).await;
+            (api, request)
+        }));
+    }
+
+    /// Sends the request once it is signed. It runs in its own task, so it
+    /// makes progress while the current page is consumed.
+    fn poll_build(&mut self, cx: &mut Context<'_>) -> Poll<anyhow::Result<()>> {
+        let Some(building) = &mut self.building else {
+            return Poll::Ready(Ok(()));
+        };
+        let (api, request) = ready!(building.as_mut().poll(cx));
+        self.building = None;
+        self.api = Some(api);
+        self.pending = Some(tokio::spawn(execute_request(request?)));
+        Poll::Ready(Ok(()))
+    }
+}
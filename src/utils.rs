@@ -30,6 +30,27 @@ pub fn get_json_value<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
     current_value
 }
 
+/// Depth-first search for the first non-null value stored under `key`.
+pub fn find_json_key<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    match value {
+        Value::Object(map) => map
+            .get(key)
+            .filter(|v| !v.is_null())
+            .or_else(|| map.values().find_map(|v| find_json_key(v, key))),
+        Value::Array(items) => items.iter().find_map(|v| find_json_key(v, key)),
+        _ => None,
+    }
+}
+
+/// Accepts a share code or a share URL such as `https://poe.com/s/<code>`.
+pub fn parse_share_code(input: &str) -> &str {
+    let code = match input.split_once("/s/") {
+        Some((_, rest)) => rest,
+        None => input,
+    };
+    code.split(['?', '#', '/']).next().unwrap_or(code).trim()
+}
+
 pub async fn generate_file(files: &[FileInput<'_>]) -> anyhow::Result<Vec<FileData>> {
     let client = Client::builder()
         .default_headers(default_headers())